@@ -0,0 +1,54 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::Error;
+
+// Preset every config can use, selects the whole component list
+pub const PRESET_ALL: &str = "all";
+
+// Everything a headless install would otherwise ask for, command line flags take priority
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct AnswerFile {
+    pub path: Option<String>,
+    pub channel: Option<String>,
+    pub target: Option<String>,
+    // Component or category ids
+    #[serde(default)]
+    pub components: Vec<String>,
+    // Named selections from bootstrapper.toml
+    #[serde(default)]
+    pub presets: Vec<String>,
+    // Install into a path that already has files in it but isn't a recognised installation
+    #[serde(default)]
+    pub overwrite: bool,
+    #[serde(default)]
+    pub launch_after: bool,
+}
+
+impl AnswerFile {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let data = std::fs::read_to_string(path)?;
+        toml::from_str(&data).map_err(|e| Error::ReadConfigError(format!("invalid answer file {:?}: {}", path, e)))
+    }
+}
+
+// Closest candidates to a mistyped value, best first
+pub fn close_matches<'a>(value: &str, candidates: &'a [String]) -> Vec<&'a str> {
+    let mut scored: Vec<(f64, &str)> = candidates.iter()
+        .map(|c| (strsim::jaro_winkler(&value.to_lowercase(), &c.to_lowercase()), c.as_str()))
+        .filter(|(score, _)| *score >= 0.8)
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.into_iter().take(3).map(|(_, c)| c).collect()
+}
+
+pub fn unknown_value_message(kind: &str, value: &str, candidates: &[String]) -> String {
+    let matches = close_matches(value, candidates);
+    if matches.is_empty() {
+        format!("Unknown {} {}", kind, value)
+    } else {
+        format!("Unknown {} {}, did you mean {}?", kind, value, matches.join(", "))
+    }
+}
@@ -0,0 +1,106 @@
+use std::{path::{Path, PathBuf}, time::SystemTime};
+
+use reqwest::Url;
+
+use crate::{config::AppConfig, installer::{partial_download_dir, ExpectedHashes}};
+
+// Verified component downloads kept between installs, named by component id and hash
+#[derive(Clone)]
+pub struct DownloadCache {
+    dir: PathBuf,
+    size_limit: u64,
+}
+
+impl DownloadCache {
+    // Disabled unless a size limit is configured
+    pub fn from_config(config: &AppConfig) -> Option<Self> {
+        if config.download_cache_limit_mb == 0 {
+            return None;
+        }
+        let dir = match config.download_cache_dir.as_ref() {
+            Some(dir) => PathBuf::from(dir),
+            None => partial_download_dir().join("cache"),
+        };
+        Some(DownloadCache {
+            dir,
+            size_limit: config.download_cache_limit_mb * 1024 * 1024,
+        })
+    }
+
+    // Downloads without any hash to check can't be addressed by their contents, so are never cached
+    fn entry_path(&self, id: &str, hashes: &ExpectedHashes) -> Option<PathBuf> {
+        let hash = match hashes.sha256.as_ref() {
+            Some(sha256) => sha256.clone(),
+            None if hashes.crc32 != "00000000" => hashes.crc32.clone(),
+            None => return None,
+        };
+        Some(self.dir.join(format!("{}-{}.zip", id, hash)))
+    }
+
+    // Url of a cached download, which is marked as recently used
+    pub fn lookup(&self, id: &str, hashes: &ExpectedHashes) -> Option<String> {
+        let path = self.entry_path(id, hashes)?;
+        if !path.is_file() {
+            return None;
+        }
+        let _ = touch(&path);
+        Url::from_file_path(&path).ok().map(|url| url.to_string())
+    }
+
+    pub async fn insert(&self, id: &str, hashes: &ExpectedHashes, download: &Path) -> std::io::Result<()> {
+        let path = match self.entry_path(id, hashes) {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if tokio::fs::metadata(download).await?.len() > self.size_limit {
+            return Ok(());
+        }
+        tokio::fs::create_dir_all(&self.dir).await?;
+        // Copy under another name first so an interrupted copy is never mistaken for a cached download
+        let tmp_path = path.with_extension("zip.tmp");
+        tokio::fs::copy(download, &tmp_path).await?;
+        tokio::fs::rename(&tmp_path, &path).await?;
+        // Some platforms keep the original modified time when copying
+        touch(&path)?;
+        self.evict(&path)
+    }
+
+    pub fn remove(&self, id: &str, hashes: &ExpectedHashes) {
+        if let Some(path) = self.entry_path(id, hashes) {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    // Remove the least recently used downloads until the cache fits in its size limit
+    fn evict(&self, keep: &Path) -> std::io::Result<()> {
+        let mut entries = vec![];
+        let mut total_size = 0;
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "zip") {
+                continue;
+            }
+            let metadata = std::fs::metadata(&path)?;
+            total_size += metadata.len();
+            entries.push((metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH), metadata.len(), path));
+        }
+        entries.sort_by_key(|(modified, _, _)| *modified);
+
+        for (_, size, path) in entries {
+            if total_size <= self.size_limit {
+                break;
+            }
+            if path != keep && std::fs::remove_file(&path).is_ok() {
+                total_size -= size;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn touch(path: &Path) -> std::io::Result<()> {
+    std::fs::File::options()
+        .write(true)
+        .open(path)?
+        .set_modified(SystemTime::now())
+}
@@ -0,0 +1,470 @@
+use std::collections::HashSet;
+
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::manifest::InstallManifest;
+
+// Component list as published for a channel, and resolving which components a selection needs
+
+fn deserialize_bool<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s: String = Deserialize::deserialize(deserializer)?;
+
+    match s.as_str() {
+        "1" => Ok(true),
+        "0" => Ok(false),
+        _ => Ok(false),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ComponentList {
+    #[serde(rename = "url", default)]
+    pub url: String,
+    #[serde(alias = "mirror", default)]
+    pub mirrors: Vec<String>,
+    #[serde(rename = "categories", alias = "category", default)]
+    categories: Vec<Category>,
+    #[serde(default)]
+    pub selected: Vec<String>,
+    #[serde(default)]
+    pub required: Vec<String>,
+    // Installed components that have changed since they were installed
+    #[serde(default)]
+    pub updates: Vec<String>,
+    // Installed components with missing or modified files
+    #[serde(default)]
+    pub repairs: Vec<String>,
+}
+
+impl Default for ComponentList {
+    fn default() -> Self {
+        ComponentList {
+            url: "Example Component List".to_owned(),
+            mirrors: vec![],
+            categories: vec![],
+            selected: vec![],
+            required: vec![],
+            updates: vec![],
+            repairs: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Category {
+    id: String,
+    #[serde(alias = "title")]
+    name: String,
+    description: String,
+    // This field can either be a nested category or a component. Depending on your XML structure and needs, you might need to adjust the handling.
+    #[serde(alias = "category", default)]
+    subcategories: Vec<Category>,
+    // Optional so categories holding only subcategories still parse, mirrored bundles can leave these behind
+    #[serde(alias = "component", default)]
+    components: Vec<Component>,
+    #[serde(default)]
+    required: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Component {
+    pub id: String,
+    #[serde(alias = "title")]
+    pub name: String,
+    pub description: String,
+    #[serde(alias = "date-modified")]
+    pub date_modified: String,
+    #[serde(alias = "download-size")]
+    pub download_size: u64,
+    #[serde(alias = "install-size")]
+    pub install_size: u64,
+    pub path: Option<String>,
+    pub hash: String,
+    #[serde(default)]
+    pub sha256: Option<String>,
+    pub depends: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_bool")]
+    pub required: bool,
+    #[serde(default)]
+    pub installed: bool,
+    // Files users are expected to edit, kept as they are if changed since install
+    #[serde(default)]
+    pub preserve: Option<String>,
+    // Like preserve, but the new version is written alongside the changed file with a .new extension
+    #[serde(default)]
+    pub merge: Option<String>,
+}
+
+impl Component {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn protected_paths(&self) -> Vec<String> {
+        let mut paths = split_paths(&self.preserve);
+        paths.extend(split_paths(&self.merge));
+        paths
+    }
+}
+
+// Ids are made unique by prefixing the id of the category they're in
+pub fn nested_id(parent_id: &str, id: &str) -> String {
+    if parent_id.is_empty() {
+        id.to_owned()
+    } else {
+        format!("{}-{}", parent_id, id)
+    }
+}
+
+fn update_ids_in_category(
+    category: &mut Category,
+    working_id: &str,
+) {
+    // Update own ID
+    let new_working_id = nested_id(working_id, &category.id);
+    category.id = new_working_id.clone();
+
+    for subcat in &mut category.subcategories {
+        update_ids_in_category(subcat, &new_working_id);
+    }
+
+    // Update all components
+    for comp in &mut category.components {
+        comp.id = nested_id(&new_working_id, &comp.id);
+    }
+}
+
+impl ComponentList {
+    pub fn setup(&mut self) {
+        let mut required = vec![];
+        for category in &mut self.categories {
+            // Update all IDs to be correct inside category
+            update_ids_in_category(category, ""); 
+        }
+        for category in self.categories.iter() {
+            // Add to the list of required components and categories
+            self.find_required(category, &mut required);
+        }
+        required.sort();
+        required.dedup();
+        self.required = required.clone();
+    }
+
+    fn find_required(
+        &self,
+        category: &Category,
+        list: &mut Vec<String>
+    ) -> bool {
+        let mut is_required = true;
+        // Find non-required comps
+        for component in category.components.iter() {
+            if component.required {
+                // Add all dependants
+                let mut dependencies = self.find_dependencies(&component.id);
+                list.append(&mut dependencies);
+            } else {
+                is_required = false;
+            }
+        }
+        for subcat in category.subcategories.iter() {
+            if subcat.required {
+                // If required itself, add all child components
+                let mut subcat_comps = vec![];
+                collect_category_components(subcat, &mut subcat_comps);
+                for comp in subcat_comps {
+                    list.push(comp.id.clone());
+                }
+                list.push(subcat.id.clone());
+            } else {
+                // If the category isn't stated required, then we'll decide by checking if all children are required instead
+                let required = self.find_required(subcat, list);
+                if !required {
+                    is_required = false;
+                }
+            }
+     
+        }
+        // Finally can add to list
+        if is_required {
+            list.push(category.id.clone());
+        }
+        is_required
+    }    
+
+    // pub fn mark_required(&mut self, id: &str) {
+    //     let mut required = vec![];
+    //     let components = get_all_components(&self);
+
+    //     if components.iter().find(|&c| c.id == id).is_some() {
+    //         // Is a component
+    //         self.find_dependants_recursive(id, &components, &mut required);
+    //         required.push(id.to_owned());
+    //     } else if let Some(category) = self.find_category_by_id(id) {
+    //         // Is a category
+    //         let mut category_components = vec![];
+    //         collect_category_components(category, &mut category_components);
+    //         for comp in category_components.iter() {
+    //             self.find_dependants_recursive(&comp.id, &components, &mut required);
+    //             required.push(comp.id.clone());
+    //         }
+    //     }
+
+    //     self.required.append(&mut required);
+    //     self.required.sort();
+    //     self.required.dedup();
+    // }
+
+    // Relative urls are resolved against the url the list was downloaded from
+    pub fn resolve_urls(&mut self, list_url: &str) {
+        let base = match Url::parse(list_url) {
+            Ok(base) => base,
+            Err(_) => return,
+        };
+        for url in std::iter::once(&mut self.url).chain(self.mirrors.iter_mut()) {
+            if url.trim().is_empty() || Url::parse(url.trim()).is_ok() {
+                continue;
+            }
+            if let Ok(resolved) = base.join(url.trim()) {
+                *url = resolved.to_string();
+            }
+        }
+    }
+
+    // Every category and component id, for checking ids that came from outside the list
+    pub fn known_ids(&self) -> Vec<String> {
+        let mut ids = vec![];
+        collect_category_ids(&self.categories, &mut ids);
+        ids.extend(get_all_components(self).iter().map(|c| c.id.clone()));
+        ids
+    }
+
+    pub fn select(&mut self, id: &str) {
+        let mut dependencies = self.find_dependencies(id);
+
+        self.selected.append(&mut dependencies);
+        self.selected.sort();
+        self.selected.dedup();
+    }
+
+    pub fn unselect(&mut self, id: &str) {
+        let dependants = self.find_dependants(id);
+
+        // Should be faster?
+        let dependants_set: HashSet<String> = dependants.into_iter().collect();
+
+        self.selected.retain(|e| !dependants_set.contains(e)); // Do not remove required component
+
+    }
+
+    // Resolve the final set of components to install from selected and required, including all dependencies
+    pub fn resolve_selection(&self) -> Vec<&Component> {
+        let mut ids: HashSet<String> = HashSet::new();
+        for id in self.selected.iter().chain(self.required.iter()) {
+            // Handles both component and category ids
+            ids.extend(self.find_dependencies(id));
+        }
+
+        // Keep the order of the component list, skipping anything already installed and up to date
+        get_all_components(self)
+            .into_iter()
+            .filter(|c| ids.contains(&c.id))
+            .filter(|c| !c.installed || self.updates.contains(&c.id) || self.repairs.contains(&c.id))
+            .collect()
+    }
+
+    // Mark what is already installed and find which of those have changed since
+    pub fn apply_manifest(&mut self, manifest: &InstallManifest) {
+        let installed: Vec<String> = manifest.components.keys().cloned().collect();
+        self.mark_installed(&installed);
+
+        let mut updates = vec![];
+        let mut installed_ids = vec![];
+        for comp in get_all_components(self) {
+            if let Some(entry) = manifest.components.get(&comp.id) {
+                installed_ids.push(comp.id.clone());
+                if entry.hash != comp.hash || entry.date_modified != comp.date_modified {
+                    updates.push(comp.id.clone());
+                }
+            }
+        }
+        self.updates = updates;
+
+        // Installed components stay selected, only the changed ones are downloaded again
+        for id in installed_ids {
+            self.select(&id);
+        }
+    }
+
+    pub fn mark_installed(&mut self, installed: &[String]) {
+        for category in &mut self.categories {
+            mark_installed_in_category(category, installed);
+        }
+    }
+
+    pub fn find_dependants(&self, id: &str) -> Vec<String> {
+        let mut dependants: Vec<String> = Vec::new();
+        let components = get_all_components(&self);
+
+        if components.iter().find(|&c| c.id == id).is_some() {
+            // Is a component
+            self.find_dependants_recursive(id, &components, &mut dependants);
+            dependants.push(id.to_owned());
+        } else if let Some(category) = self.find_category_by_id(id) {
+            // Is a category
+            let mut category_components = vec![];
+            collect_category_components(category, &mut category_components);
+            for comp in category_components.iter() {
+                self.find_dependants_recursive(&comp.id, &components, &mut dependants);
+                dependants.push(comp.id.clone());
+            }
+        }
+
+        // Since recursion can add duplicates, ensure unique elements
+        dependants.retain(|e| !self.required.contains(e)); // Remove all required components from dependants list
+        dependants.sort();
+        dependants.dedup();
+
+        dependants
+    }
+
+    fn find_dependants_recursive<'a>(
+        &self,
+        id: &str,
+        components: &Vec<&'a Component>,
+        dependants: &mut Vec<String>,
+    ) {
+        for component in components.iter() {
+            if let Some(depends) = &component.depends {
+                let dependencies: Vec<&str> = depends.split_whitespace().collect();
+
+                if dependencies.contains(&id) {
+                    // If not already included, add to dependants and search for its dependants
+                    if !dependants.contains(&component.id) {
+                        dependants.push(component.id.clone());
+                        self.find_dependants_recursive(&component.id, components, dependants);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn find_dependencies(&self, id: &str) -> Vec<String> {
+        let mut dependencies: Vec<String> = Vec::new();
+        let components = get_all_components(&self);
+
+        if let Some(component) = components.iter().find(|&c| c.id == id) {
+            // Is a component
+            self.find_dependencies_recursive(component, &components, &mut dependencies);
+            // Always select self as well as dependencies
+            dependencies.push(id.to_owned());
+        } else if let Some(category) = self.find_category_by_id(id) {
+            // Is a category
+            let mut category_components = vec![];
+            collect_category_components(category, &mut category_components);
+            for comp in category_components.iter() {
+                self.find_dependencies_recursive(comp, &components, &mut dependencies);
+                dependencies.push(comp.id.clone());
+            }
+        }
+        // Since recursion might add duplicates, ensure unique elements
+        dependencies.sort();
+        dependencies.dedup();
+
+        dependencies
+    }
+
+    fn find_dependencies_recursive<'a>(
+        &self,
+        component: &'a Component,
+        components: &Vec<&'a Component>,
+        dependencies: &mut Vec<String>,
+    ) {
+        if let Some(depends) = &component.depends {
+            let direct_dependencies: Vec<&str> = depends.split_whitespace().collect();
+
+            for dep_id in direct_dependencies.iter() {
+                // Avoid adding duplicate dependencies
+                if !dependencies.contains(&dep_id.to_string()) {
+                    dependencies.push(dep_id.to_string());
+
+                    // Find the component that matches this dependency ID and recursively find its dependencies
+                    if let Some(dep_component) = components.iter().find(|&c| &c.id == *dep_id) {
+                        self.find_dependencies_recursive(dep_component, components, dependencies);
+                    }
+                }
+            }
+        }
+    }
+
+    // Function to find a category by ID and return a reference to it
+    fn find_category_by_id(&self, id: &str) -> Option<&Category> {
+        self.find_category_recursive(&self.categories, id)
+    }
+
+    fn find_category_recursive<'a>(&'a self, categories: &'a [Category], id: &str) -> Option<&'a Category> {
+        for category in categories {
+            if category.id == id {
+                return Some(category);
+            }
+            if let Some(subcategory) = self.find_category_recursive(&category.subcategories, id) {
+                return Some(subcategory);
+            }
+        }
+        None
+    }
+}
+
+fn mark_installed_in_category(category: &mut Category, installed: &[String]) {
+    for subcat in &mut category.subcategories {
+        mark_installed_in_category(subcat, installed);
+    }
+    for comp in &mut category.components {
+        comp.installed = installed.contains(&comp.id);
+    }
+}
+
+fn collect_category_components<'a>(category: &'a Category, components: &mut Vec<&'a Component>) {
+    for subcat in category.subcategories.iter() {
+        collect_category_components(subcat, components);
+    }
+    for component in &category.components {
+        components.push(component);
+    }
+}
+
+fn collect_components<'a>(categories: &'a [Category], components: &mut Vec<&'a Component>) {
+    for category in categories {
+        // Add all components in the current category to the components vector
+        collect_category_components(&category, components);
+    }
+}
+
+fn collect_category_ids(categories: &[Category], ids: &mut Vec<String>) {
+    for category in categories {
+        ids.push(category.id.clone());
+        collect_category_ids(&category.subcategories, ids);
+    }
+}
+
+// Utility function to initiate the collection process and return the result
+pub fn get_all_components<'a>(list: &'a ComponentList) -> Vec<&'a Component> {
+    let mut components = Vec::new();
+    collect_components(&list.categories, &mut components);
+    components
+}
+
+// Path lists in the component list are separated by semicolons, since paths may contain spaces
+pub fn split_paths(paths: &Option<String>) -> Vec<String> {
+    match paths {
+        Some(paths) => paths.split(';')
+            .map(|p| p.trim())
+            .filter(|p| !p.is_empty())
+            .map(|p| p.to_owned())
+            .collect(),
+        None => vec![],
+    }
+}
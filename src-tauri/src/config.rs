@@ -8,6 +8,54 @@ pub(crate) struct AppConfig {
     pub windows: Option<OsConfig>,
     pub linux: Option<OsConfig>,
     pub macos: Option<OsConfig>,
+    #[serde(default = "default_max_concurrent_downloads")]
+    pub max_concurrent_downloads: usize,
+    #[serde(default = "default_download_attempts")]
+    pub download_attempts: u32,
+    // Seconds to wait for a connection, and for the next bytes of a response, before retrying
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    #[serde(default = "default_read_timeout_secs")]
+    pub read_timeout_secs: u64,
+    #[serde(default)]
+    pub probe_mirrors: bool,
+    #[serde(default)]
+    pub require_strong_hash: bool,
+    // Relative to the installation path, never removed when a component update stops shipping them
+    #[serde(default)]
+    pub preserved_paths: Vec<String>,
+    // Local directory or bundle archive with components.xml and the component zips, used instead of the channels
+    #[serde(default)]
+    pub offline_source: Option<String>,
+    // Verified downloads are kept here between installs, defaults to the system temp directory
+    #[serde(default)]
+    pub download_cache_dir: Option<String>,
+    // Least recently used downloads are removed past this size, 0 disables the cache
+    #[serde(default)]
+    pub download_cache_limit_mb: u64,
+    // Components are extracted into a new directory here, defaults to the installation path.
+    // Should be on the same volume as the installation so files can be renamed into place
+    #[serde(default)]
+    pub staging_dir: Option<String>,
+    // Named component selections answer files can refer to
+    #[serde(default)]
+    pub presets: HashMap<String, Vec<String>>,
+}
+
+fn default_max_concurrent_downloads() -> usize {
+    4
+}
+
+fn default_download_attempts() -> u32 {
+    5
+}
+
+fn default_connect_timeout_secs() -> u64 {
+    30
+}
+
+fn default_read_timeout_secs() -> u64 {
+    60
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -16,6 +64,11 @@ pub(crate) struct OsConfig {
     pub relative_executable: String,
     pub channels: HashMap<String, String>,
     pub default_channel: String,
+    #[serde(default)]
+    pub mirrors: Vec<String>,
+    // Minisign public keys (base64) trusted to sign the component lists, unsigned lists are accepted if empty
+    #[serde(default)]
+    pub public_keys: Vec<String>,
 }
 
 impl Default for AppConfig {
@@ -30,9 +83,23 @@ impl Default for AppConfig {
                 relative_executable: "./Launcher/Flashpoint.exe".to_owned(),
                 default_channel: "Stable".to_owned(),
                 channels: default_channels,
+                mirrors: vec![],
+                public_keys: vec![],
             }),
             linux: None,
             macos: None,
+            max_concurrent_downloads: default_max_concurrent_downloads(),
+            download_attempts: default_download_attempts(),
+            connect_timeout_secs: default_connect_timeout_secs(),
+            read_timeout_secs: default_read_timeout_secs(),
+            probe_mirrors: false,
+            require_strong_hash: false,
+            preserved_paths: vec![],
+            offline_source: None,
+            download_cache_dir: None,
+            download_cache_limit_mb: 0,
+            staging_dir: None,
+            presets: HashMap::new(),
         }
     }
 }
@@ -0,0 +1,119 @@
+use std::{io::Write, sync::Mutex};
+
+use serde::Serialize;
+use serde_json::Value;
+use tauri::Window;
+
+use crate::installer::readable_byte_size;
+
+// Receives everything the installer reports, so the same install can run behind the window, a console or nothing at all
+pub trait EventSink: Send + Sync {
+    fn send(&self, event: &str, payload: Value);
+}
+
+impl dyn EventSink + '_ {
+    pub fn emit<S: Serialize>(&self, event: &str, payload: S) {
+        self.send(event, serde_json::to_value(payload).unwrap_or(Value::Null));
+    }
+}
+
+pub struct WindowSink(pub Window);
+
+impl EventSink for WindowSink {
+    fn send(&self, event: &str, payload: Value) {
+        // A window that's gone can't show the error either
+        if let Err(e) = self.0.emit(event, payload) {
+            eprintln!("Failed to send {} to the window - {}", event, e);
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ConsoleSink(Mutex<ConsoleProgress>);
+
+impl EventSink for ConsoleSink {
+    fn send(&self, event: &str, payload: Value) {
+        self.0.lock().unwrap().print(event, &payload);
+    }
+}
+
+// One JSON object per line on stdout, with the same event names and payloads the window gets
+pub struct JsonSink;
+
+impl EventSink for JsonSink {
+    fn send(&self, event: &str, payload: Value) {
+        let line = serde_json::json!({ "event": event, "payload": payload });
+        let mut stdout = std::io::stdout().lock();
+        let _ = writeln!(stdout, "{}", line);
+        let _ = stdout.flush();
+    }
+}
+
+// Keeps every event in order, for tests driving the installer without a window
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemorySink {
+    events: Mutex<Vec<(String, Value)>>,
+}
+
+#[cfg(test)]
+impl MemorySink {
+    pub fn events(&self) -> Vec<(String, Value)> {
+        self.events.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+impl EventSink for MemorySink {
+    fn send(&self, event: &str, payload: Value) {
+        self.events.lock().unwrap().push((event.to_owned(), payload));
+    }
+}
+
+// Prints events as readable lines, progress only when the stage, component or percentage changes
+#[derive(Default)]
+struct ConsoleProgress {
+    last_line: String,
+    // Component id and attempt of every retry already printed
+    retries: Vec<(String, u64)>,
+}
+
+impl ConsoleProgress {
+    fn print(&mut self, event: &str, payload: &Value) {
+        match event {
+            "download_state" => self.print_download_state(payload),
+            "stage" => println!("{}", payload.as_str().unwrap_or_default()),
+            "warning" => eprintln!("Warning: {}", payload.as_str().unwrap_or_default()),
+            "fatal_error" => eprintln!("Error: {}", payload.as_str().unwrap_or_default()),
+            "installation_cancelled" => println!("Installation cancelled"),
+            "installation_finished" => println!("Installation finished"),
+            _ => (),
+        }
+    }
+
+    fn print_download_state(&mut self, state: &Value) {
+        for progress in state["in_progress"].as_array().into_iter().flatten() {
+            let id = progress["component"]["id"].as_str().unwrap_or_default().to_owned();
+            let attempt = progress["attempt"].as_u64().unwrap_or(1);
+            if attempt > 1 && !self.retries.contains(&(id.clone(), attempt)) {
+                println!("Retrying {} (attempt {}) - {}", id, attempt, progress["last_error"].as_str().unwrap_or_default());
+                self.retries.push((id, attempt));
+            }
+        }
+
+        let total_size = state["total_size"].as_u64().unwrap_or(0);
+        let total_downloaded = state["total_downloaded"].as_u64().unwrap_or(0).min(total_size);
+        let percent = (total_downloaded * 100).checked_div(total_size).unwrap_or(0);
+        let line = format!(
+            "{} - component {} of {} - {}%",
+            state["stage"].as_str().unwrap_or_default(),
+            state["component_number"].as_u64().unwrap_or(0),
+            state["total_components"].as_u64().unwrap_or(0),
+            percent,
+        );
+        if line != self.last_line {
+            println!("{} ({} of {})", line, readable_byte_size(total_downloaded), readable_byte_size(total_size));
+            self.last_line = line;
+        }
+    }
+}
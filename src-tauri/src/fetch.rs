@@ -0,0 +1,228 @@
+use std::{collections::HashMap, io::SeekFrom, sync::Arc, time::Duration};
+
+use futures::{future::BoxFuture, stream::BoxStream, FutureExt, StreamExt, TryStreamExt};
+use reqwest::Url;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::{config::AppConfig, offline::local_path};
+
+pub type FetchError = Box<dyn std::error::Error + Send + Sync>;
+
+pub struct FetchResponse {
+    pub stream: BoxStream<'static, Result<Vec<u8>, FetchError>>,
+    // Whether the stream starts at the requested offset, otherwise it starts from the beginning
+    pub resumed: bool,
+}
+
+// Reads the body behind a url, one backend per url scheme
+pub trait Fetcher: Send + Sync {
+    fn fetch<'a>(&'a self, url: &'a str, offset: u64) -> BoxFuture<'a, Result<FetchResponse, FetchError>>;
+}
+
+pub struct HttpFetcher {
+    client: reqwest::Client,
+}
+
+impl HttpFetcher {
+    // Without timeouts a stalled connection would hang forever instead of being retried
+    pub fn new(connect_timeout: Duration, read_timeout: Duration) -> Self {
+        HttpFetcher {
+            client: reqwest::Client::builder()
+                .connect_timeout(connect_timeout)
+                .read_timeout(read_timeout)
+                .build()
+                .expect("Failed to create http client"),
+        }
+    }
+}
+
+impl Fetcher for HttpFetcher {
+    fn fetch<'a>(&'a self, url: &'a str, offset: u64) -> BoxFuture<'a, Result<FetchResponse, FetchError>> {
+        async move {
+            let mut response = if offset > 0 {
+                self.client.get(url)
+                    .header(reqwest::header::RANGE, format!("bytes={}-", offset))
+                    .send()
+                    .await?
+            } else {
+                self.client.get(url).send().await?
+            };
+            if offset > 0 && response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+                // Recorded progress doesn't match the file on the server anymore, start over
+                response = self.client.get(url).send().await?;
+            }
+            let response = response.error_for_status()?;
+            let resumed = response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+            // Body errors stay reqwest errors so they're still retried
+            let stream = response.bytes_stream()
+                .map(|chunk| chunk.map(|c| c.to_vec()).map_err(|e| e.into()));
+            Ok(FetchResponse { stream: stream.boxed(), resumed })
+        }.boxed()
+    }
+}
+
+// Offline sources and local mirrors, read straight from disk
+pub struct FileFetcher;
+
+impl Fetcher for FileFetcher {
+    fn fetch<'a>(&'a self, url: &'a str, offset: u64) -> BoxFuture<'a, Result<FetchResponse, FetchError>> {
+        async move {
+            let path = local_path(url).ok_or_else(|| format!("Invalid file url {}", url))?;
+            let mut source = tokio::fs::File::open(&path).await?;
+            let resumed = offset > 0 && offset <= source.metadata().await?.len();
+            if resumed {
+                source.seek(SeekFrom::Start(offset)).await?;
+            }
+            let stream = futures::stream::try_unfold(source, |mut source| async move {
+                let mut buf = vec![0u8; 64 * 1024];
+                let read = source.read(&mut buf).await?;
+                if read == 0 {
+                    return Ok::<_, FetchError>(None);
+                }
+                buf.truncate(read);
+                Ok(Some((buf, source)))
+            });
+            Ok(FetchResponse { stream: stream.boxed(), resumed })
+        }.boxed()
+    }
+}
+
+// Fixed contents keyed by url, for tests with lists and components that never touch the disk or network
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryFetcher {
+    files: std::sync::Mutex<HashMap<String, Vec<u8>>>,
+}
+
+#[cfg(test)]
+impl MemoryFetcher {
+    pub fn insert(&self, url: &str, data: Vec<u8>) {
+        self.files.lock().unwrap().insert(url.to_owned(), data);
+    }
+}
+
+#[cfg(test)]
+impl Fetcher for MemoryFetcher {
+    fn fetch<'a>(&'a self, url: &'a str, offset: u64) -> BoxFuture<'a, Result<FetchResponse, FetchError>> {
+        async move {
+            let data = self.files.lock().unwrap().get(url).cloned()
+                .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, format!("{} not found", url)))?;
+            let resumed = offset > 0 && offset <= data.len() as u64;
+            let data = if resumed { data[offset as usize..].to_vec() } else { data };
+            Ok(FetchResponse { stream: futures::stream::once(async { Ok(data) }).boxed(), resumed })
+        }.boxed()
+    }
+}
+
+// Picks the fetcher for a url by its scheme, http(s) and file are always available
+#[derive(Clone)]
+pub struct Transport {
+    fetchers: HashMap<String, Arc<dyn Fetcher>>,
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Transport::from_config(&AppConfig::default())
+    }
+}
+
+impl Transport {
+    pub fn from_config(config: &AppConfig) -> Self {
+        let http: Arc<dyn Fetcher> = Arc::new(HttpFetcher::new(
+            Duration::from_secs(config.connect_timeout_secs.max(1)),
+            Duration::from_secs(config.read_timeout_secs.max(1)),
+        ));
+        let mut transport = Transport { fetchers: HashMap::new() };
+        transport.register("http", http.clone());
+        transport.register("https", http);
+        transport.register("file", Arc::new(FileFetcher));
+        transport
+    }
+
+    pub fn register(&mut self, scheme: &str, fetcher: Arc<dyn Fetcher>) {
+        self.fetchers.insert(scheme.to_lowercase(), fetcher);
+    }
+
+    // Only downloads that went over the network are worth keeping a copy of
+    pub fn is_network_url(url: &str) -> bool {
+        matches!(url_scheme(url).as_deref(), Some("http") | Some("https"))
+    }
+
+    pub async fn bytes(&self, url: &str) -> Result<Vec<u8>, FetchError> {
+        let response = self.fetch(url, 0).await?;
+        let chunks: Vec<Vec<u8>> = response.stream.try_collect().await?;
+        Ok(chunks.concat())
+    }
+
+    pub async fn text(&self, url: &str) -> Result<String, FetchError> {
+        Ok(String::from_utf8(self.bytes(url).await?)?)
+    }
+}
+
+impl Fetcher for Transport {
+    fn fetch<'a>(&'a self, url: &'a str, offset: u64) -> BoxFuture<'a, Result<FetchResponse, FetchError>> {
+        let fetcher = url_scheme(url).and_then(|scheme| self.fetchers.get(&scheme));
+        match fetcher {
+            Some(fetcher) => fetcher.fetch(url, offset),
+            None => {
+                let err: FetchError = format!("Unsupported url {}", url).into();
+                futures::future::ready(Err(err)).boxed()
+            },
+        }
+    }
+}
+
+fn url_scheme(url: &str) -> Option<String> {
+    Url::parse(url).ok().map(|u| u.scheme().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{offline::directory_url, staging::StagingDir};
+
+    fn fetch_all(fetcher: &dyn Fetcher, url: &str, offset: u64) -> (Vec<u8>, bool) {
+        tauri::async_runtime::block_on(async {
+            let response = fetcher.fetch(url, offset).await.unwrap();
+            let chunks: Vec<Vec<u8>> = response.stream.try_collect().await.unwrap();
+            (chunks.concat(), response.resumed)
+        })
+    }
+
+    fn fixture(data: &[u8]) -> (StagingDir, String) {
+        let dir = StagingDir::create(&std::env::temp_dir().join("fp-bootstrapper-tests")).unwrap();
+        std::fs::write(dir.path().join("fixture.zip"), data).unwrap();
+        let url = directory_url(dir.path()).unwrap() + "fixture.zip";
+        (dir, url)
+    }
+
+    #[test]
+    fn file_fetcher_resumes_within_file() {
+        let (_dir, url) = fixture(b"0123456789");
+        assert_eq!(fetch_all(&FileFetcher, &url, 4), (b"456789".to_vec(), true));
+        assert_eq!(fetch_all(&FileFetcher, &url, 10), (vec![], true));
+    }
+
+    #[test]
+    fn file_fetcher_restarts_beyond_file() {
+        let (_dir, url) = fixture(b"0123456789");
+        assert_eq!(fetch_all(&FileFetcher, &url, 11), (b"0123456789".to_vec(), false));
+        assert_eq!(fetch_all(&FileFetcher, &url, 0), (b"0123456789".to_vec(), false));
+    }
+
+    #[test]
+    fn transport_uses_registered_memory_fetcher() {
+        let fetcher = Arc::new(MemoryFetcher::default());
+        fetcher.insert("memory://mirror/list.xml", b"<list/>".to_vec());
+        let mut transport = Transport::default();
+        transport.register("memory", fetcher.clone());
+
+        let text = tauri::async_runtime::block_on(transport.text("memory://mirror/list.xml")).unwrap();
+        assert_eq!(text, "<list/>");
+        assert_eq!(fetch_all(fetcher.as_ref(), "memory://mirror/list.xml", 2), (b"ist/>".to_vec(), true));
+
+        let missing = tauri::async_runtime::block_on(transport.bytes("memory://mirror/other.xml")).unwrap_err();
+        assert_eq!(missing.downcast_ref::<std::io::Error>().map(|e| e.kind()), Some(std::io::ErrorKind::NotFound));
+        assert!(tauri::async_runtime::block_on(transport.bytes("ftp://mirror/list.xml")).is_err());
+    }
+}
@@ -0,0 +1,313 @@
+use std::{path::{Path, PathBuf}, process::Command, sync::Arc};
+
+use crate::{answers::{unknown_value_message, AnswerFile, PRESET_ALL}, components::get_all_components, events::{ConsoleSink, EventSink, JsonSink}, installer::InstallOutcome, mirror, state::AppState, Error, OperatingSystem, View};
+
+pub const EXIT_SUCCESS: i32 = 0;
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_BAD_ARGUMENTS: i32 = 2;
+pub const EXIT_NETWORK_FAILURE: i32 = 3;
+pub const EXIT_INTEGRITY_FAILURE: i32 = 4;
+
+pub const USAGE: &str = "Usage: fp-bootstrapper --headless [--answers <file>] [--path <dir>] [--channel <name>] [--target <windows|linux|macos>] [--select <id,id,...>] [--preset <name,...>] [--overwrite] [--launch-after] [--json]";
+
+// Install without a window, going through the same views the window would
+pub struct HeadlessOptions {
+    pub path: Option<String>,
+    pub channel: Option<String>,
+    pub target: Option<OperatingSystem>,
+    // Component or category ids, installed along with the required components
+    pub select: Vec<String>,
+    pub presets: Vec<String>,
+    pub overwrite: bool,
+    pub launch_after: bool,
+    pub answers: Option<PathBuf>,
+}
+
+impl HeadlessOptions {
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut options = HeadlessOptions {
+            path: None,
+            channel: None,
+            target: None,
+            select: vec![],
+            presets: vec![],
+            overwrite: false,
+            launch_after: false,
+            answers: None,
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().cloned().ok_or_else(|| format!("Missing value for {}", arg));
+            match arg.as_str() {
+                // Handled before parsing, so errors in the other arguments are reported the right way
+                "--headless" | "--json" => (),
+                "--answers" => options.answers = Some(PathBuf::from(value()?)),
+                "--path" => options.path = Some(value()?),
+                "--channel" => options.channel = Some(value()?),
+                "--target" => options.target = Some(value()?.parse()?),
+                "--select" => options.select.extend(split_list(&value()?)),
+                "--preset" => options.presets.extend(split_list(&value()?)),
+                "--overwrite" => options.overwrite = true,
+                "--launch-after" => options.launch_after = true,
+                _ => return Err(format!("Unknown argument {}", arg)),
+            }
+        }
+        Ok(options)
+    }
+
+    // Fill in anything not given on the command line
+    pub fn apply_answers(&mut self, answers: AnswerFile) -> Result<(), String> {
+        if self.path.is_none() {
+            self.path = answers.path;
+        }
+        if self.channel.is_none() {
+            self.channel = answers.channel;
+        }
+        if self.target.is_none() {
+            self.target = answers.target.map(|t| t.parse()).transpose()?;
+        }
+        self.select.extend(answers.components);
+        self.presets.extend(answers.presets);
+        self.overwrite |= answers.overwrite;
+        self.launch_after |= answers.launch_after;
+        Ok(())
+    }
+}
+
+fn split_list(value: &str) -> impl Iterator<Item = String> + '_ {
+    value.split(',')
+        .map(|v| v.trim().to_owned())
+        .filter(|v| !v.is_empty())
+}
+
+// Release builds on Windows have no console of their own, so print to the one we were started from
+#[cfg(target_os = "windows")]
+pub fn attach_console() {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn attach_console() {}
+
+pub fn exit_code(outcome: InstallOutcome) -> i32 {
+    match outcome {
+        InstallOutcome::Finished => EXIT_SUCCESS,
+        InstallOutcome::NetworkFailure => EXIT_NETWORK_FAILURE,
+        InstallOutcome::IntegrityFailure => EXIT_INTEGRITY_FAILURE,
+        InstallOutcome::Cancelled | InstallOutcome::Failed => EXIT_FAILURE,
+    }
+}
+
+// Progress as readable lines, or as JSON for tools wrapping the bootstrapper
+pub fn events_for_args(args: &[String]) -> Arc<dyn EventSink> {
+    if args.iter().any(|a| a == "--json") {
+        Arc::new(JsonSink)
+    } else {
+        Arc::new(ConsoleSink::default())
+    }
+}
+
+pub fn run(state: AppState, args: &[String]) -> i32 {
+    let events = events_for_args(args);
+    if let Some(fe) = state.fatal_error.as_ref() {
+        events.emit("fatal_error", fe);
+        return EXIT_BAD_ARGUMENTS;
+    }
+    let mut options = match HeadlessOptions::from_args(args) {
+        Ok(options) => options,
+        Err(e) => {
+            events.emit("fatal_error", format!("{}\n{}", e, USAGE));
+            return EXIT_BAD_ARGUMENTS;
+        }
+    };
+    if let Some(path) = options.answers.clone() {
+        let applied = AnswerFile::load(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|answers| options.apply_answers(answers));
+        if let Err(e) = applied {
+            events.emit("fatal_error", e);
+            return EXIT_BAD_ARGUMENTS;
+        }
+    }
+    tauri::async_runtime::block_on(install(state, options, events))
+}
+
+async fn install(mut state: AppState, options: HeadlessOptions, events: Arc<dyn EventSink>) -> i32 {
+    if let Some(target) = options.target.clone() {
+        state.installation_target = target;
+        // Adjusting would fall back to windows, which isn't what was asked for
+        if state.os_config().is_none() {
+            events.emit("fatal_error", "Selected platform does not have an installation candidate");
+            return EXIT_BAD_ARGUMENTS;
+        }
+        state.adjust_installation_target();
+    }
+    let os_config = match state.os_config() {
+        Some(os_config) => os_config,
+        None => {
+            events.emit("fatal_error", "Selected platform does not have an installation candidate");
+            return EXIT_BAD_ARGUMENTS;
+        },
+    };
+    if let Some(path) = options.path.clone() {
+        state.installation_path = path;
+    }
+    state.overwrite_existing = options.overwrite;
+    if let Some(channel) = options.channel.clone() {
+        // Offline sources have a single list, whichever channel is named
+        if state.config.offline_source.is_none() && !os_config.channels.contains_key(&channel) {
+            let mut channels: Vec<String> = os_config.channels.keys().cloned().collect();
+            channels.sort();
+            events.emit("fatal_error", unknown_value_message("channel", &channel, &channels));
+            return EXIT_BAD_ARGUMENTS;
+        }
+        state.installation_channel = channel;
+    } else {
+        state.installation_channel = os_config.default_channel;
+    }
+
+    events.emit("stage", format!("Fetching component list for channel {}", state.installation_channel));
+    if let Err(e) = state.change_view(View::SETUPSELECT, events.clone()).await {
+        return report_error(events.as_ref(), &e);
+    }
+    if let Err(errors) = select_components(&mut state, &options) {
+        for e in errors {
+            events.emit("fatal_error", e);
+        }
+        return EXIT_BAD_ARGUMENTS;
+    }
+
+    events.emit("stage", format!("Installing to {}", state.installation_path));
+    if let Err(e) = state.change_view(View::INSTALLATION, events.clone()).await {
+        return report_error(events.as_ref(), &e);
+    }
+    let handle = state.task_handle.lock().unwrap().take();
+    let outcome = match handle {
+        Some(handle) => match handle.await {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                events.emit("fatal_error", e.to_string());
+                InstallOutcome::Failed
+            },
+        },
+        None => InstallOutcome::Failed,
+    };
+    if outcome == InstallOutcome::Finished && options.launch_after {
+        launch(&state, events.as_ref());
+    }
+    exit_code(outcome)
+}
+
+// Check every id and preset against the fetched list before selecting anything, reporting all mistakes at once
+fn select_components(state: &mut AppState, options: &HeadlessOptions) -> Result<(), Vec<String>> {
+    let mut errors = vec![];
+    let mut ids = options.select.clone();
+
+    let mut preset_names: Vec<String> = state.config.presets.keys().cloned().collect();
+    preset_names.push(PRESET_ALL.to_owned());
+    for preset in options.presets.iter() {
+        if preset == PRESET_ALL {
+            ids.extend(get_all_components(&state.components).iter().map(|c| c.id().to_owned()));
+        } else if let Some(preset_ids) = state.config.presets.get(preset) {
+            ids.extend(preset_ids.iter().cloned());
+        } else {
+            errors.push(unknown_value_message("preset", preset, &preset_names));
+        }
+    }
+
+    let known_ids = state.components.known_ids();
+    for id in ids.iter() {
+        if !known_ids.contains(id) {
+            errors.push(unknown_value_message("component or category", id, &known_ids));
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    for id in ids.iter() {
+        state.components.select(id);
+    }
+    Ok(())
+}
+
+fn launch(state: &AppState, events: &dyn EventSink) {
+    let executable = match state.os_config() {
+        Some(os_config) => Path::new(&state.installation_path).join(os_config.relative_executable),
+        None => return,
+    };
+    let mut command = Command::new(&executable);
+    if let Some(dir) = executable.parent() {
+        command.current_dir(dir);
+    }
+    // Installation already succeeded, so failing to launch doesn't change the exit code
+    match command.spawn() {
+        Ok(_) => events.emit("stage", format!("Launched {}", executable.display())),
+        Err(e) => events.emit("warning", format!("Failed to launch {} - {}", executable.display(), e)),
+    }
+}
+
+fn report_error(events: &dyn EventSink, err: &Error) -> i32 {
+    events.emit("fatal_error", err.to_string());
+    exit_code(InstallOutcome::from_error(err))
+}
+
+pub fn run_mirror(state: AppState, args: &[String]) -> i32 {
+    let events = events_for_args(args);
+    if let Some(fe) = state.fatal_error.as_ref() {
+        events.emit("fatal_error", fe);
+        return EXIT_BAD_ARGUMENTS;
+    }
+    let options = match mirror::MirrorOptions::from_args(args) {
+        Ok(options) => options,
+        Err(e) => {
+            events.emit("fatal_error", format!("{}\n{}", e, mirror::USAGE));
+            return EXIT_BAD_ARGUMENTS;
+        }
+    };
+    match tauri::async_runtime::block_on(mirror::mirror_channel(state, options, events.clone())) {
+        Ok(()) => EXIT_SUCCESS,
+        Err(e) => report_error(events.as_ref(), &e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::MemorySink;
+
+    fn install_with_args(args: &[&str]) -> (i32, Vec<String>) {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        let options = HeadlessOptions::from_args(&args).unwrap();
+        let sink = Arc::new(MemorySink::default());
+        let code = tauri::async_runtime::block_on(install(AppState::default(), options, sink.clone()));
+        let errors = sink.events().into_iter()
+            .filter(|(event, _)| event == "fatal_error")
+            .map(|(_, payload)| payload.as_str().unwrap_or_default().to_owned())
+            .collect();
+        (code, errors)
+    }
+
+    #[test]
+    fn unconfigured_target_is_a_bad_argument() {
+        // The default config only has a windows entry
+        let (code, errors) = install_with_args(&["--headless", "--target", "linux"]);
+        assert_eq!(code, EXIT_BAD_ARGUMENTS);
+        assert_eq!(errors, vec!["Selected platform does not have an installation candidate"]);
+    }
+
+    #[test]
+    fn unknown_channel_is_a_bad_argument() {
+        let (code, errors) = install_with_args(&["--headless", "--target", "windows", "--channel", "Stabel"]);
+        assert_eq!(code, EXIT_BAD_ARGUMENTS);
+        assert_eq!(errors, vec!["Unknown channel Stabel, did you mean Stable?"]);
+    }
+}
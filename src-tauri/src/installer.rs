@@ -0,0 +1,941 @@
+use std::{collections::HashMap, future::Future, io::{Seek, SeekFrom}, path::{Path, PathBuf}, pin::pin, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, time::{Duration, Instant}};
+
+use crc32fast::Hasher;
+use futures::{future::Either, StreamExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::{io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt}, sync::OwnedSemaphorePermit};
+use walkdir::WalkDir;
+use zip::ZipArchive;
+
+use crate::{cache::DownloadCache, components::{split_paths, Component}, events::EventSink, manifest::{hash_file, is_preserved_path, manifest_path_string, prune_empty_dirs, InstallManifest, ManifestComponent, ManifestFile}, fetch::{FetchResponse, Fetcher, Transport}, transaction::InstallTransaction, Error};
+
+// Downloading, verifying and extracting components, reporting progress through an EventSink
+// so none of it depends on the window
+
+fn remove_readonly_attr(path: &std::path::Path) -> std::io::Result<()> {
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::fs::MetadataExt;
+        let metadata = fs::metadata(path)?;
+        if metadata.file_attributes() & 0x1 != 0 { // FILE_ATTRIBUTE_READONLY
+            let mut options = OpenOptions::new();
+            options.write(true).custom_flags(0x80000000); // FILE_ATTRIBUTE_NORMAL
+            let _file = options.open(path)?;
+            // Opening the file with FILE_ATTRIBUTE_NORMAL is enough to clear the readonly attribute.
+        }
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut permissions = std::fs::metadata(path)?.permissions();
+        permissions.set_mode(0o644);
+        std::fs::set_permissions(path, permissions)?;
+    }
+    Ok(())
+}
+
+fn check_cancelled(cancel_flag: &AtomicBool) -> Result<(), Error> {
+    if cancel_flag.load(Ordering::SeqCst) {
+        return Err(Error::Cancelled);
+    }
+    Ok(())
+}
+
+pub fn is_cancelled_error(err: &(dyn std::error::Error + 'static)) -> bool {
+    matches!(err.downcast_ref::<Error>(), Some(Error::Cancelled))
+}
+
+// Transient network failures and corrupt downloads are worth another attempt, anything else is permanent
+fn is_retryable_error(err: &(dyn std::error::Error + 'static)) -> bool {
+    if let Some(err) = err.downcast_ref::<Error>() {
+        return matches!(err, Error::HashMismatch(_));
+    }
+    if let Some(err) = err.downcast_ref::<reqwest::Error>() {
+        if let Some(status) = err.status() {
+            return status.is_server_error()
+                || status == reqwest::StatusCode::REQUEST_TIMEOUT
+                || status == reqwest::StatusCode::TOO_MANY_REQUESTS;
+        }
+        return err.is_timeout() || err.is_connect() || err.is_request() || err.is_body() || err.is_decode();
+    }
+    false
+}
+
+fn retry_delay(attempt: u32) -> Duration {
+    // 1s, 2s, 4s... capped at a minute
+    Duration::from_secs(2u64.pow(attempt.saturating_sub(1).min(6)).min(60))
+}
+
+// Run until the future completes or the installation is cancelled, so a stalled connection can't hold up a cancel
+async fn or_cancel<T>(future: impl Future<Output = T>, cancel_flag: &AtomicBool) -> Result<T, Error> {
+    let cancelled = async {
+        while !cancel_flag.load(Ordering::SeqCst) {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    };
+    match futures::future::select(pin!(future), pin!(cancelled)).await {
+        Either::Left((res, _)) => Ok(res),
+        Either::Right(_) => Err(Error::Cancelled),
+    }
+}
+
+async fn wait_or_cancel(delay: Duration, cancel_flag: &AtomicBool) -> Result<(), Error> {
+    check_cancelled(cancel_flag)?;
+    or_cancel(tokio::time::sleep(delay), cancel_flag).await
+}
+
+// Order mirrors by how quickly they respond, unreachable mirrors go last.
+// Only http(s) mirrors are probed, local and other mirrors have no network latency and go first
+pub async fn sort_mirrors_by_latency(mirrors: Vec<String>) -> Vec<String> {
+    let client = match reqwest::Client::builder().timeout(Duration::from_secs(5)).build() {
+        Ok(client) => client,
+        Err(_) => return mirrors,
+    };
+    let probes = mirrors.into_iter().map(|mirror| {
+        let client = client.clone();
+        async move {
+            if !Transport::is_network_url(&mirror) {
+                return (mirror, Some(Duration::ZERO));
+            }
+            let start = Instant::now();
+            let latency = client.head(&mirror).send().await.ok().map(|_| start.elapsed());
+            (mirror, latency)
+        }
+    });
+    let mut results = futures::future::join_all(probes).await;
+    results.sort_by_key(|(_, latency)| latency.unwrap_or(Duration::MAX));
+    results.into_iter().map(|(mirror, _)| mirror).collect()
+}
+
+// Where component zips can be downloaded from, the cache is always checked first
+#[derive(Clone)]
+pub struct DownloadSources {
+    pub mirrors: Vec<String>,
+    pub cache: Option<DownloadCache>,
+    pub transport: Transport,
+}
+
+pub async fn download_component(comp: &Component, sources: &DownloadSources, max_attempts: u32, events: &dyn EventSink, state: &Arc<Mutex<DownloadState>>, cancel_flag: &AtomicBool) -> Result<std::fs::File, Box<dyn std::error::Error + Send + Sync>> {
+    // Download each component zip to a file, extracted later by install_component
+    let part_path = partial_download_path(comp);
+    let hashes = ExpectedHashes::from_component(comp);
+    let mut attempt = 1;
+
+    // A verified download from an earlier install skips the network entirely
+    if let Some(cache) = sources.cache.as_ref() {
+        if let Some(url) = cache.lookup(&comp.id, &hashes) {
+            match download_file_tmp(&url, comp, &sources.transport, events, state, cancel_flag).await {
+                Ok(file) => return Ok(file),
+                Err(e) if is_cancelled_error(e.as_ref()) => return Err(e),
+                Err(e) => {
+                    // Corrupt or unreadable, download it again instead
+                    events.emit("warning", format!("Cached download of {} is unusable, downloading it again - {}", comp.id, e));
+                    state.lock().unwrap().reset_downloaded(&comp.id);
+                    cache.remove(&comp.id, &hashes);
+                },
+            }
+        }
+    }
+
+    loop {
+        // Fail over between mirrors before backing off, the partial download carries over between them
+        let mut last_error: Option<Box<dyn std::error::Error + Send + Sync>> = None;
+        let mut retryable = false;
+        for mirror in sources.mirrors.iter() {
+            let url = mirror.to_owned() + &comp.id + ".zip";
+            state.lock().unwrap().set_component_mirror(&comp.id, mirror);
+            match download_file_tmp(&url, comp, &sources.transport, events, state, cancel_flag).await {
+                Ok(file) => {
+                    state.lock().unwrap().mirrors.insert(comp.id.clone(), mirror.clone());
+                    // Local sources are already on disk, no need to copy them into the cache too
+                    if let (Some(cache), true) = (sources.cache.as_ref(), Transport::is_network_url(&url)) {
+                        // Caching is best effort, the download itself already succeeded
+                        if let Err(e) = cache.insert(&comp.id, &hashes, &part_path).await {
+                            events.emit("warning", format!("Failed to cache download of {} - {}", comp.id, e));
+                        }
+                    }
+                    return Ok(file);
+                },
+                Err(e) if is_cancelled_error(e.as_ref()) => return Err(e),
+                Err(e) => {
+                    state.lock().unwrap().reset_downloaded(&comp.id);
+                    retryable |= is_retryable_error(e.as_ref());
+                    last_error = Some(e);
+                },
+            }
+        }
+
+        let e = last_error.unwrap_or_else(|| Error::GeneralError("No download mirrors available".to_owned()).into());
+        if attempt >= max_attempts || !retryable {
+            return Err(e);
+        }
+        attempt += 1;
+        {
+            let mut state = state.lock().unwrap();
+            state.retry_component(&comp.id, attempt, &e.to_string());
+            events.emit("download_state", state.clone());
+        }
+        wait_or_cancel(retry_delay(attempt - 1), cancel_flag).await?;
+        state.lock().unwrap().set_component_stage(&comp.id, "Downloading");
+    }
+}
+
+// The download slot is held until extracted so finished downloads don't pile up on disk
+pub async fn download_component_queued(comp: Component, sources: DownloadSources, max_attempts: u32, events: Arc<dyn EventSink>, state: Arc<Mutex<DownloadState>>, permit: OwnedSemaphorePermit, cancel_flag: Arc<AtomicBool>) -> Result<(std::fs::File, OwnedSemaphorePermit), Box<dyn std::error::Error + Send + Sync>> {
+    check_cancelled(&cancel_flag)?;
+    {
+        let mut state = state.lock().unwrap();
+        state.start_component(&comp);
+        events.emit("download_state", state.clone());
+    }
+    let file = download_component(&comp, &sources, max_attempts, events.as_ref(), &state, &cancel_flag).await?;
+    Ok((file, permit))
+}
+
+pub fn install_component(comp: &Component, file: std::fs::File, previous: Option<&ManifestComponent>, transaction: &mut InstallTransaction, events: &dyn EventSink, state: &Arc<Mutex<DownloadState>>, cancel_flag: &AtomicBool) -> Result<Vec<ManifestFile>, Box<dyn std::error::Error + Send + Sync>> {
+    let base_dir = transaction.base_dir().to_path_buf();
+    let temp_dir_path = &transaction.staging_dir().path().to_path_buf();
+    let base_dir_temp = &match &comp.path {
+        Some(path) => temp_dir_path.join(path),
+        None => temp_dir_path.clone(),
+    };
+    let preserved_paths = split_paths(&comp.preserve);
+    let merged_paths = split_paths(&comp.merge);
+
+    std::fs::create_dir_all(base_dir_temp)?;
+
+    // Extract to the staging folder
+    let mut archive = ZipArchive::new(file)?;
+
+    {
+        let mut state = state.lock().unwrap();
+        state.component_number += 1;
+        state.set_component_stage(&comp.id, "Extracting");
+        events.emit("download_state", state.clone());
+    }
+
+    for i in 0..archive.len() {
+        check_cancelled(cancel_flag)?;
+        let mut file = archive.by_index(i)?;
+        let outpath = match file.enclosed_name() {
+            Some(path) => base_dir_temp.join(path),
+            None => continue,
+        };
+
+        if (&*file.name()).ends_with('/') {
+            // Create a directory if the file is a directory
+            std::fs::create_dir_all(&outpath)?;
+        } else {
+            // Ensure the file's parent directory exists
+            if let Some(p) = outpath.parent() {
+                if !p.exists() {
+                    std::fs::create_dir_all(&p)?;
+                }
+            }
+
+            // Write the file content
+            let mut outfile = std::fs::File::create(&outpath)?;
+            std::io::copy(&mut file, &mut outfile)?;
+        }
+    }
+
+    // Download is no longer needed once extracted
+    drop(archive);
+    remove_partial_download(&partial_download_path(comp));
+
+    // Last safe point to stop, once copying begins the component is finished first
+    check_cancelled(cancel_flag)?;
+
+    // Move files from staging to main dir, recording each one for the manifest
+    let mut files = vec![];
+    for entry in WalkDir::new(temp_dir_path).into_iter().filter_map(|e| e.ok()) {
+        let path = entry.path();
+        let relative_path = path.strip_prefix(temp_dir_path)?;
+        let dest_path = base_dir.join(relative_path);
+
+        if path.is_dir() {
+            transaction.create_dir_all(&dest_path)?;
+        } else if path.is_file() {
+            let manifest_path = manifest_path_string(relative_path);
+            let merge = is_preserved_path(&manifest_path, &merged_paths);
+            if (merge || is_preserved_path(&manifest_path, &preserved_paths)) && is_user_modified(&dest_path, &manifest_path, previous) {
+                // Keep the user's copy, recording the shipped file so it still counts as changed next time
+                let shipped = ManifestFile::from_path(temp_dir_path, path)?;
+                if merge {
+                    let mut new_path = dest_path.clone().into_os_string();
+                    new_path.push(".new");
+                    let new_path = PathBuf::from(new_path);
+                    let _ = remove_readonly_attr(&new_path);
+                    transaction.move_file(path, &new_path)?;
+                    files.push(ManifestFile::from_path(&base_dir, &new_path)?);
+                }
+                files.push(shipped);
+                continue;
+            }
+            let _ = remove_readonly_attr(&dest_path); // If it fails, the move error will present itself soon and bubble up later
+            transaction.move_file(path, &dest_path)?;
+            files.push(ManifestFile::from_path(&base_dir, &dest_path)?);
+        }
+    }
+
+    transaction.staging_dir().clear()?;
+    
+    Ok(files)
+}
+
+// File exists and no longer matches what was installed, or was never installed by us at all
+fn is_user_modified(path: &Path, manifest_path: &str, previous: Option<&ManifestComponent>) -> bool {
+    if !path.is_file() {
+        return false;
+    }
+    match previous.and_then(|p| p.files.iter().find(|f| f.path == manifest_path)) {
+        Some(installed) => match hash_file(path) {
+            Ok((size, sha256)) => size != installed.size || sha256 != installed.sha256,
+            Err(_) => true,
+        },
+        None => true,
+    }
+}
+
+// Remove files an earlier version of the component shipped that the new version doesn't
+pub fn remove_orphaned_files(comp: &Component, files: &[ManifestFile], manifest: &InstallManifest, base_dir: &str, preserved_paths: &[String], transaction: &mut InstallTransaction) -> std::io::Result<()> {
+    let base_dir = Path::new(base_dir);
+    let mut preserved_paths = preserved_paths.to_vec();
+    preserved_paths.extend(comp.protected_paths());
+    for orphan in manifest.orphaned_files(&comp.id, files, &preserved_paths) {
+        let path = base_dir.join(&orphan);
+        let _ = remove_readonly_attr(&path);
+        transaction.remove_file(&path)?;
+        prune_empty_dirs(base_dir, &path);
+    }
+    Ok(())
+}
+
+// Progress of a partial download, stored next to the .part file so it can be resumed
+#[derive(Serialize, Deserialize)]
+struct PartialDownload {
+    length: u64,
+    crc32: u32,
+}
+
+// Partial downloads live outside the installation path so a restart can still find them
+pub fn partial_download_dir() -> PathBuf {
+    std::env::temp_dir().join("fp-bootstrapper")
+}
+
+pub fn partial_download_path(comp: &Component) -> PathBuf {
+    partial_download_dir().join(format!("{}-{}.part", comp.id, comp.hash.to_uppercase()))
+}
+
+fn partial_progress_path(part_path: &Path) -> PathBuf {
+    let mut progress_path = part_path.as_os_str().to_owned();
+    progress_path.push(".json");
+    PathBuf::from(progress_path)
+}
+
+pub fn remove_partial_download(part_path: &Path) {
+    let _ = std::fs::remove_file(part_path);
+    let _ = std::fs::remove_file(partial_progress_path(part_path));
+}
+
+fn load_partial_download(part_path: &Path) -> Option<PartialDownload> {
+    let data = std::fs::read_to_string(partial_progress_path(part_path)).ok()?;
+    let progress: PartialDownload = serde_json::from_str(&data).ok()?;
+    // Progress is written after the file is flushed, so the file can only be longer than recorded
+    let file_len = std::fs::metadata(part_path).ok()?.len();
+    if file_len < progress.length {
+        return None;
+    }
+    Some(progress)
+}
+
+fn save_partial_download(part_path: &Path, progress: &PartialDownload) -> std::io::Result<()> {
+    let data = serde_json::to_string(progress)?;
+    std::fs::write(partial_progress_path(part_path), data)
+}
+
+// Hashes a download is checked against, CRC32 of 00000000 means no CRC32 check
+pub struct ExpectedHashes {
+    pub crc32: String,
+    pub sha256: Option<String>,
+}
+
+impl ExpectedHashes {
+    pub fn from_component(comp: &Component) -> Self {
+        ExpectedHashes {
+            crc32: comp.hash.to_uppercase(),
+            sha256: comp.sha256.as_deref().map(str::trim).filter(|h| !h.is_empty()).map(str::to_lowercase),
+        }
+    }
+
+    pub fn can_verify(&self) -> bool {
+        self.crc32 != "00000000" || self.sha256.is_some()
+    }
+}
+
+// SHA-256 progress isn't persisted, so rehash what's already on disk when resuming
+async fn hash_partial_file(file: &mut tokio::fs::File, length: u64, hasher: &mut Sha256) -> std::io::Result<()> {
+    file.seek(SeekFrom::Start(0)).await?;
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut remaining = length;
+    while remaining > 0 {
+        let to_read = remaining.min(buf.len() as u64) as usize;
+        let read = file.read(&mut buf[..to_read]).await?;
+        if read == 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "Partial download is shorter than recorded"));
+        }
+        hasher.update(&buf[..read]);
+        remaining -= read as u64;
+    }
+    Ok(())
+}
+
+pub async fn download_file_tmp(url: &str, comp: &Component, fetcher: &dyn Fetcher, events: &dyn EventSink, state: &Arc<Mutex<DownloadState>>, cancel_flag: &AtomicBool) -> Result<std::fs::File, Box<dyn std::error::Error + Send + Sync>> {
+    let id = comp.id();
+    let part_path = &partial_download_path(comp);
+    let hashes = &ExpectedHashes::from_component(comp);
+    if let Some(parent) = part_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut tmp_file = tokio::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(part_path)
+        .await?;
+
+    // Pick up where a previous attempt left off, if it recorded any progress.
+    // Without a hash nothing would catch a partial file left by a different version, so always start over
+    let resumable = hashes.can_verify();
+    let progress = if resumable { load_partial_download(part_path) } else { None };
+    let (mut offset, mut hasher) = match progress {
+        Some(progress) => (progress.length, Hasher::new_with_initial_len(progress.crc32, progress.length)),
+        None => (0, Hasher::new()),
+    };
+
+    let FetchResponse { stream: mut byte_stream, resumed } = or_cancel(fetcher.fetch(url, offset), cancel_flag).await??;
+    if !resumed {
+        // Server doesn't support ranges (or wasn't asked), full download
+        offset = 0;
+        hasher = Hasher::new();
+    }
+    tmp_file.set_len(offset).await?;
+    let crc32_hash = hashes.crc32.as_str();
+    let sha256_hash = hashes.sha256.as_deref();
+    let mut sha256_hasher = sha256_hash.map(|_| Sha256::new());
+    if let Some(sha256_hasher) = sha256_hasher.as_mut() {
+        hash_partial_file(&mut tmp_file, offset, sha256_hasher).await?;
+    }
+    tmp_file.seek(SeekFrom::Start(offset)).await?;
+    state.lock().unwrap().add_downloaded(id, offset);
+
+    let mut last_call = Instant::now();
+
+    while let Some(item) = or_cancel(byte_stream.next(), cancel_flag).await? {
+        // Partial file is removed by the installer when cancelled
+        check_cancelled(cancel_flag)?;
+        let chunk = item?;
+        hasher.update(&chunk);
+        if let Some(sha256_hasher) = sha256_hasher.as_mut() {
+            sha256_hasher.update(&chunk);
+        }
+        tmp_file.write_all(&chunk).await?;
+        offset += chunk.len() as u64;
+
+        let emit = last_call.elapsed() >= Duration::from_millis(200);
+        {
+            let mut state = state.lock().unwrap();
+            state.add_downloaded(id, chunk.len() as u64);
+            if emit {
+                events.emit("download_state", state.clone());
+            }
+        }
+        if emit {
+            if resumable {
+                // Record progress so the download can be resumed later
+                tmp_file.flush().await?;
+                save_partial_download(part_path, &PartialDownload {
+                    length: offset,
+                    crc32: hasher.clone().finalize(),
+                })?;
+            }
+            last_call = Instant::now();
+        }
+    }
+    tmp_file.flush().await?;
+
+    if crc32_hash != "00000000" {
+        let calculated_hash = hasher.finalize();
+        let calculated_hash_str = format!("{:08x}", calculated_hash);
+        if calculated_hash_str.to_uppercase() != crc32_hash {
+            // Corrupt download, don't try to resume from it
+            drop(tmp_file);
+            remove_partial_download(part_path);
+            let msg = format!("Download failed, hash mismatch: Got {:?} expected {:?} - URL: {:?}", calculated_hash_str.to_uppercase(), crc32_hash, url);
+            return Err(Box::new(crate::Error::HashMismatch(msg)));
+        }
+    }
+
+    if let (Some(expected), Some(sha256_hasher)) = (sha256_hash, sha256_hasher) {
+        let calculated_hash_str = format!("{:x}", sha256_hasher.finalize());
+        if calculated_hash_str != expected {
+            drop(tmp_file);
+            remove_partial_download(part_path);
+            let msg = format!("Download failed, SHA-256 mismatch: Got {:?} expected {:?} - URL: {:?}", calculated_hash_str, expected, url);
+            return Err(Box::new(crate::Error::HashMismatch(msg)));
+        }
+    }
+    let _ = std::fs::remove_file(partial_progress_path(part_path));
+
+    {
+        let mut state = state.lock().unwrap();
+        state.set_component_stage(id, "Waiting to extract");
+        events.emit("download_state", state.clone());
+    }
+
+    let mut file = tmp_file.into_std().await;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(file)
+}
+
+pub fn readable_byte_size(bytes: u64) -> String {
+    let units = ["B", "KB", "MB", "GB", "TB", "PB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, units[unit])
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
+
+// How an installation run ended, headless runs exit with a different code for each
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum InstallOutcome {
+    Finished,
+    Cancelled,
+    NetworkFailure,
+    IntegrityFailure,
+    Failed,
+}
+
+impl InstallOutcome {
+    pub fn from_error(err: &(dyn std::error::Error + 'static)) -> Self {
+        if let Some(err) = err.downcast_ref::<Error>() {
+            return match err {
+                Error::Cancelled => InstallOutcome::Cancelled,
+                Error::NetworkError(_) => InstallOutcome::NetworkFailure,
+                Error::HashMismatch(_) | Error::SignatureError(_) => InstallOutcome::IntegrityFailure,
+                _ => InstallOutcome::Failed,
+            };
+        }
+        if err.downcast_ref::<reqwest::Error>().is_some() {
+            return InstallOutcome::NetworkFailure;
+        }
+        // Zips are only opened once their hashes have been checked, so this is a bad archive on the server
+        if err.downcast_ref::<zip::result::ZipError>().is_some() {
+            return InstallOutcome::IntegrityFailure;
+        }
+        InstallOutcome::Failed
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct InstallationCancelled {
+    pub installed: Vec<String>,
+    pub not_installed: Vec<String>,
+    // Components that had finished installing but were removed again
+    pub rolled_back: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ComponentProgress {
+    pub component: Component,
+    pub downloaded: u64,
+    pub stage: String,
+    pub attempt: u32,
+    pub last_error: Option<String>,
+    pub mirror: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DownloadState {
+    pub total_size: u64,
+    pub total_downloaded: u64,
+    pub total_components: usize,
+    pub component_number: i32,
+    pub in_progress: Vec<ComponentProgress>,
+    // Mirror that served each downloaded component, by component id
+    pub mirrors: HashMap<String, String>,
+    pub stage: String,
+}
+
+impl Default for DownloadState {
+    fn default() -> Self {
+        DownloadState {
+            total_size: 0,
+            total_downloaded: 0,
+            total_components: 0,
+            component_number: 0,
+            in_progress: vec![],
+            mirrors: HashMap::new(),
+            stage: String::new(),
+        }
+    }
+}
+
+impl DownloadState {
+    pub fn start_component(&mut self, comp: &Component) {
+        self.in_progress.push(ComponentProgress {
+            component: comp.clone(),
+            downloaded: 0,
+            stage: "Downloading".to_owned(),
+            attempt: 1,
+            last_error: None,
+            mirror: None,
+        });
+    }
+
+    pub fn retry_component(&mut self, id: &str, attempt: u32, error: &str) {
+        if let Some(progress) = self.in_progress.iter_mut().find(|p| p.component.id == id) {
+            progress.stage = "Retrying".to_owned();
+            progress.attempt = attempt;
+            progress.last_error = Some(error.to_owned());
+        }
+    }
+
+    pub fn add_downloaded(&mut self, id: &str, amount: u64) {
+        self.total_downloaded += amount;
+        if let Some(progress) = self.in_progress.iter_mut().find(|p| p.component.id == id) {
+            progress.downloaded += amount;
+        }
+    }
+
+    // Downloaded bytes are counted again when the next attempt resumes
+    pub fn reset_downloaded(&mut self, id: &str) {
+        if let Some(progress) = self.in_progress.iter_mut().find(|p| p.component.id == id) {
+            self.total_downloaded -= progress.downloaded;
+            progress.downloaded = 0;
+        }
+    }
+
+    pub fn set_component_mirror(&mut self, id: &str, mirror: &str) {
+        if let Some(progress) = self.in_progress.iter_mut().find(|p| p.component.id == id) {
+            progress.mirror = Some(mirror.to_owned());
+        }
+    }
+
+    pub fn set_component_stage(&mut self, id: &str, stage: &str) {
+        if let Some(progress) = self.in_progress.iter_mut().find(|p| p.component.id == id) {
+            progress.stage = stage.to_owned();
+        }
+    }
+
+    pub fn finish_component(&mut self, id: &str) {
+        self.in_progress.retain(|p| p.component.id != id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::write::{FileOptions, ZipWriter};
+
+    use super::*;
+    use crate::{components::ComponentList, config::AppConfig, events::MemorySink, fetch::MemoryFetcher, offline::directory_url, staging::StagingDir, state::AppState};
+
+    // Zip holding the given files, as a component would be published
+    fn component_zip(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(std::io::Cursor::new(vec![]));
+        for (name, contents) in files {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    // Partial downloads are shared between runs, so every run gets its own ids
+    fn component(name: &str, data: &[u8]) -> Component {
+        let id = format!("{}-{}", name, std::process::id());
+        Component {
+            id: id.clone(),
+            name: id,
+            description: String::new(),
+            date_modified: String::new(),
+            download_size: data.len() as u64,
+            install_size: data.len() as u64,
+            path: None,
+            hash: format!("{:08X}", crc32fast::hash(data)),
+            sha256: Some(format!("{:x}", Sha256::digest(data))),
+            depends: None,
+            required: false,
+            installed: false,
+            preserve: None,
+            merge: None,
+        }
+    }
+
+    fn test_dir() -> StagingDir {
+        StagingDir::create(&std::env::temp_dir().join("fp-bootstrapper-tests")).unwrap()
+    }
+
+    fn event_names(sink: &MemorySink) -> Vec<String> {
+        sink.events().into_iter().map(|(event, _)| event).collect()
+    }
+
+    #[test]
+    fn install_component_extracts_files_and_reports_progress() {
+        let dir = test_dir();
+        let data = component_zip(&[("readme.txt", "hello"), ("data/config.ini", "a=1")]);
+        let comp = component("install", &data);
+        let zip_path = dir.path().join("install.zip");
+        std::fs::write(&zip_path, &data).unwrap();
+
+        let base_dir = dir.path().join("Flashpoint");
+        let mut transaction = InstallTransaction::begin(&base_dir, None).unwrap();
+        let sink = MemorySink::default();
+        let state = Arc::new(Mutex::new(DownloadState::default()));
+        state.lock().unwrap().start_component(&comp);
+
+        let file = std::fs::File::open(&zip_path).unwrap();
+        let files = install_component(&comp, file, None, &mut transaction, &sink, &state, &AtomicBool::new(false)).unwrap();
+        transaction.commit().unwrap();
+
+        assert_eq!(files.len(), 2);
+        assert_eq!(std::fs::read_to_string(base_dir.join("readme.txt")).unwrap(), "hello");
+        assert_eq!(std::fs::read_to_string(base_dir.join("data").join("config.ini")).unwrap(), "a=1");
+        let events = sink.events();
+        assert_eq!(event_names(&sink), vec!["download_state"]);
+        assert_eq!(events[0].1["component_number"], 1);
+        assert_eq!(events[0].1["in_progress"][0]["stage"], "Extracting");
+    }
+
+    #[test]
+    fn download_component_reports_progress_until_verified() {
+        let dir = test_dir();
+        let data = component_zip(&[("readme.txt", "hello")]);
+        let comp = component("download", &data);
+        std::fs::write(dir.path().join(format!("{}.zip", comp.id)), &data).unwrap();
+        let sources = DownloadSources {
+            mirrors: vec![directory_url(dir.path()).unwrap()],
+            cache: None,
+            transport: Transport::default(),
+        };
+
+        let sink = MemorySink::default();
+        let state = Arc::new(Mutex::new(DownloadState::default()));
+        state.lock().unwrap().start_component(&comp);
+        let file = tauri::async_runtime::block_on(download_component(&comp, &sources, 1, &sink, &state, &AtomicBool::new(false))).unwrap();
+        drop(file);
+
+        assert_eq!(std::fs::read(partial_download_path(&comp)).unwrap(), data);
+        remove_partial_download(&partial_download_path(&comp));
+        assert!(event_names(&sink).iter().all(|e| e == "download_state"));
+        let (_, last) = sink.events().pop().unwrap();
+        assert_eq!(last["in_progress"][0]["stage"], "Waiting to extract");
+        assert_eq!(state.lock().unwrap().mirrors.get(&comp.id), Some(&sources.mirrors[0]));
+    }
+
+    #[test]
+    fn download_component_warns_about_unusable_cache_entry() {
+        let dir = test_dir();
+        let data = component_zip(&[("readme.txt", "hello")]);
+        let comp = component("cache", &data);
+        let mirror_dir = dir.path().join("mirror");
+        std::fs::create_dir_all(&mirror_dir).unwrap();
+        std::fs::write(mirror_dir.join(format!("{}.zip", comp.id)), &data).unwrap();
+
+        // Cached under the right hashes, but not what they describe
+        let config = AppConfig {
+            download_cache_dir: Some(dir.path().join("cache").to_string_lossy().into_owned()),
+            download_cache_limit_mb: 10,
+            ..AppConfig::default()
+        };
+        let cache = DownloadCache::from_config(&config).unwrap();
+        let corrupt_path = dir.path().join("corrupt.zip");
+        std::fs::write(&corrupt_path, b"not a zip").unwrap();
+        let hashes = ExpectedHashes::from_component(&comp);
+        tauri::async_runtime::block_on(cache.insert(&comp.id, &hashes, &corrupt_path)).unwrap();
+
+        let sources = DownloadSources {
+            mirrors: vec![directory_url(&mirror_dir).unwrap()],
+            cache: Some(cache.clone()),
+            transport: Transport::default(),
+        };
+        let sink = MemorySink::default();
+        let state = Arc::new(Mutex::new(DownloadState::default()));
+        state.lock().unwrap().start_component(&comp);
+        let file = tauri::async_runtime::block_on(download_component(&comp, &sources, 1, &sink, &state, &AtomicBool::new(false))).unwrap();
+        drop(file);
+
+        assert_eq!(std::fs::read(partial_download_path(&comp)).unwrap(), data);
+        remove_partial_download(&partial_download_path(&comp));
+        let warnings: Vec<String> = sink.events().into_iter()
+            .filter(|(event, _)| event == "warning")
+            .map(|(_, payload)| payload.as_str().unwrap_or_default().to_owned())
+            .collect();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains(&comp.id));
+        assert!(cache.lookup(&comp.id, &hashes).is_none());
+    }
+
+    #[test]
+    fn download_component_retries_after_hash_mismatch() {
+        let data = component_zip(&[("readme.txt", "hello")]);
+        let comp = component("retry", &data);
+        let url = format!("memory://mirror/{}.zip", comp.id);
+        let fetcher = Arc::new(MemoryFetcher::default());
+        fetcher.insert(&url, b"corrupt".to_vec());
+        let mut transport = Transport::default();
+        transport.register("memory", fetcher.clone());
+        let sources = DownloadSources {
+            mirrors: vec!["memory://mirror/".to_owned()],
+            cache: None,
+            transport,
+        };
+
+        let sink = MemorySink::default();
+        let state = Arc::new(Mutex::new(DownloadState::default()));
+        state.lock().unwrap().start_component(&comp);
+        let retrying = |sink: &MemorySink| sink.events().iter()
+            .any(|(event, payload)| event == "download_state" && payload["in_progress"][0]["attempt"] == 2);
+        // Serve the real zip once the first attempt has failed, while the retry waits
+        let fix = async {
+            for _ in 0..500 {
+                if retrying(&sink) {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            fetcher.insert(&url, data.clone());
+        };
+        let cancel_flag = AtomicBool::new(false);
+        let download = download_component(&comp, &sources, 2, &sink, &state, &cancel_flag);
+        let (file, ()) = tauri::async_runtime::block_on(futures::future::join(download, fix));
+        drop(file.unwrap());
+
+        assert_eq!(std::fs::read(partial_download_path(&comp)).unwrap(), data);
+        remove_partial_download(&partial_download_path(&comp));
+        assert!(event_names(&sink).iter().all(|e| e == "download_state"));
+        let events = sink.events();
+        let retry = events.iter().find(|(_, payload)| payload["in_progress"][0]["attempt"] == 2).unwrap();
+        assert!(retry.1["in_progress"][0]["last_error"].as_str().unwrap().contains("hash mismatch"));
+        let (_, last) = events.last().unwrap();
+        assert_eq!(last["in_progress"][0]["stage"], "Waiting to extract");
+    }
+
+    #[test]
+    fn unverifiable_download_never_resumes() {
+        let data = component_zip(&[("readme.txt", "new version")]);
+        let mut comp = component("unhashed", &data);
+        comp.hash = "00000000".to_owned();
+        comp.sha256 = None;
+        // Left behind by an interrupted download of an older version
+        let part_path = partial_download_path(&comp);
+        std::fs::create_dir_all(part_path.parent().unwrap()).unwrap();
+        std::fs::write(&part_path, b"stale").unwrap();
+        save_partial_download(&part_path, &PartialDownload { length: 5, crc32: crc32fast::hash(b"stale") }).unwrap();
+
+        let url = format!("memory://mirror/{}.zip", comp.id);
+        let fetcher = MemoryFetcher::default();
+        fetcher.insert(&url, data.clone());
+        let state = Arc::new(Mutex::new(DownloadState::default()));
+        state.lock().unwrap().start_component(&comp);
+        let sink = MemorySink::default();
+        let file = tauri::async_runtime::block_on(download_file_tmp(&url, &comp, &fetcher, &sink, &state, &AtomicBool::new(false)));
+        drop(file.unwrap());
+
+        assert_eq!(std::fs::read(&part_path).unwrap(), data);
+        remove_partial_download(&part_path);
+    }
+
+    #[test]
+    fn latency_probe_puts_local_mirrors_first() {
+        let dir = test_dir();
+        let local = directory_url(dir.path()).unwrap();
+        // Nothing listens on port 9, so the http mirror fails its probe quickly
+        let mirrors = vec!["http://127.0.0.1:9/".to_owned(), local.clone(), "memory://mirror/".to_owned()];
+        let sorted = tauri::async_runtime::block_on(sort_mirrors_by_latency(mirrors));
+        assert_eq!(sorted, vec![local, "memory://mirror/".to_owned(), "http://127.0.0.1:9/".to_owned()]);
+    }
+
+    // Accepts the request but never sends a response, like a server that stopped answering
+    struct StalledFetcher;
+
+    impl Fetcher for StalledFetcher {
+        fn fetch<'a>(&'a self, _url: &'a str, _offset: u64) -> futures::future::BoxFuture<'a, Result<FetchResponse, crate::fetch::FetchError>> {
+            Box::pin(futures::future::pending())
+        }
+    }
+
+    #[test]
+    fn cancel_interrupts_stalled_download() {
+        let comp = component("stalled", b"data");
+        let state = Arc::new(Mutex::new(DownloadState::default()));
+        state.lock().unwrap().start_component(&comp);
+        let cancel_flag = AtomicBool::new(false);
+        let cancel = async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            cancel_flag.store(true, Ordering::SeqCst);
+        };
+        let sink = MemorySink::default();
+        let download = download_file_tmp("memory://mirror/stalled.zip", &comp, &StalledFetcher, &sink, &state, &cancel_flag);
+        let (res, ()) = tauri::async_runtime::block_on(futures::future::join(download, cancel));
+        assert!(is_cancelled_error(res.unwrap_err().as_ref()));
+        remove_partial_download(&partial_download_path(&comp));
+    }
+
+    #[test]
+    fn start_downloader_installs_and_reports_finished() {
+        let dir = test_dir();
+        let data = component_zip(&[("readme.txt", "hello")]);
+        let comp = component("core", &data);
+        let mirror_dir = dir.path().join("mirror");
+        std::fs::create_dir_all(&mirror_dir).unwrap();
+        // Component ids are prefixed with their category once the list is set up
+        std::fs::write(mirror_dir.join(format!("core-{}.zip", comp.id)), &data).unwrap();
+
+        let list = format!(
+            r#"<list url="">
+                <category id="core" name="Core" description="">
+                    <component id="{}" name="Core" description="" date-modified="" download-size="{}" install-size="{}" hash="{}" sha256="{}" required="1"/>
+                </category>
+            </list>"#,
+            comp.id, comp.download_size, comp.install_size, comp.hash, comp.sha256.as_deref().unwrap(),
+        );
+        let mut components: ComponentList = serde_xml_rs::from_str(&list).unwrap();
+        components.setup();
+
+        let mut state = AppState {
+            components,
+            installation_path: dir.path().join("Flashpoint").to_string_lossy().into_owned(),
+            offline_root: Some(mirror_dir),
+            ..AppState::default()
+        };
+        state.config.download_cache_limit_mb = 0;
+
+        let sink = Arc::new(MemorySink::default());
+        let outcome = tauri::async_runtime::block_on(async {
+            state.start_downloader(sink.clone()).await;
+            let handle = state.task_handle.lock().unwrap().take().unwrap();
+            handle.await.unwrap()
+        });
+
+        assert_eq!(outcome, InstallOutcome::Finished);
+        assert_eq!(std::fs::read_to_string(dir.path().join("Flashpoint").join("readme.txt")).unwrap(), "hello");
+        let names = event_names(&sink);
+        assert_eq!(names.first().map(String::as_str), Some("download_state"));
+        assert_eq!(names.last().map(String::as_str), Some("installation_finished"));
+        assert!(names[..names.len() - 1].iter().all(|e| e == "download_state"));
+        let (_, finished) = sink.events().into_iter().rev().find(|(event, _)| event == "download_state").unwrap();
+        assert_eq!(finished["component_number"], 1);
+        assert_eq!(finished["in_progress"].as_array().map(|p| p.len()), Some(0));
+    }
+}
@@ -4,11 +4,26 @@
 use std::sync::{Arc, PoisonError};
 
 use serde::{Deserialize, Serialize};
+use events::WindowSink;
+use fetch::Transport;
+use manifest::ComponentVerification;
 use state::AppState;
 use tauri::{async_runtime::Mutex, Manager, State, Window};
 
+mod answers;
+mod cache;
+mod components;
 mod config;
+mod events;
+mod fetch;
+mod headless;
+mod installer;
+mod manifest;
+mod mirror;
+mod offline;
+mod staging;
 mod state;
+mod transaction;
 
 #[derive(Clone, Serialize, Deserialize)]
 enum OperatingSystem {
@@ -17,12 +32,26 @@ enum OperatingSystem {
     MACOS
 }
 
+impl std::str::FromStr for OperatingSystem {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "windows" => Ok(OperatingSystem::WINDOWS),
+            "linux" => Ok(OperatingSystem::LINUX),
+            "macos" => Ok(OperatingSystem::MACOS),
+            _ => Err(format!("Unknown target {}", s)),
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub enum View {
     SETUP,
     SETUPSELECT,
     INSTALLATION,
     FINISHED,
+    ABORTED,
 }
 
 // Create a custom Error that we can return in Results
@@ -38,6 +67,14 @@ pub enum Error {
     ReadConfigError(String),
     #[error("{0}")]
     GeneralError(String),
+    #[error("installation was cancelled")]
+    Cancelled,
+    #[error("{0}")]
+    HashMismatch(String),
+    #[error("component list signature verification failed: {0}")]
+    SignatureError(String),
+    #[error("{0}")]
+    NetworkError(String),
 }
 
 // Implement Serialize for the error
@@ -86,7 +123,7 @@ async fn set_installation_path(window: Window, app_state: State<'_, Arc<Mutex<Ap
 #[tauri::command]
 async fn change_view(window: Window, app_state: State<'_, Arc<Mutex<AppState>>>, view: View) -> Result<(), Error> {
     let mut state = app_state.lock().await;
-    state.change_view(view, window.clone()).await?;
+    state.change_view(view, Arc::new(WindowSink(window.clone()))).await?;
     sync_state(&window, state.clone()).unwrap();
     Ok(())
 }
@@ -122,10 +159,39 @@ async fn unselect_component(window: Window, app_state: State<'_, Arc<Mutex<AppSt
 #[tauri::command]
 async fn installation_finished_back(window: Window, app_state: State<'_, Arc<Mutex<AppState>>>, _: i32) -> Result<AppState, Error> {
     let mut state = app_state.lock().await;
-    state.change_view(View::FINISHED, window.clone()).await?;
+    state.change_view(View::FINISHED, Arc::new(WindowSink(window.clone()))).await?;
     Ok(state.clone())
 }
 
+#[tauri::command]
+async fn uninstall_component(window: Window, app_state: State<'_, Arc<Mutex<AppState>>>, id: String, cascade: bool) -> Result<Vec<String>, Error> {
+    let mut state = app_state.lock().await;
+    let removed = state.uninstall_component(&id, cascade)?;
+    sync_state(&window, state.clone()).unwrap();
+    Ok(removed)
+}
+
+#[tauri::command]
+async fn verify_installation(window: Window, app_state: State<'_, Arc<Mutex<AppState>>>) -> Result<Vec<ComponentVerification>, Error> {
+    let mut state = app_state.lock().await;
+    let report = state.verify_installation().await?;
+    sync_state(&window, state.clone()).unwrap();
+    Ok(report)
+}
+
+#[tauri::command]
+async fn cancel_installation(window: Window, app_state: State<'_, Arc<Mutex<AppState>>>) -> Result<(), Error> {
+    // Wait for the installer without holding the state, other commands shouldn't stall while it cleans up
+    let handle = app_state.lock().await.request_cancel();
+    if let Some(handle) = handle {
+        handle.await.map_err(|e| Error::GeneralError(e.to_string()))?;
+    }
+    let mut state = app_state.lock().await;
+    state.change_view(View::ABORTED, Arc::new(WindowSink(window.clone()))).await?;
+    sync_state(&window, state.clone()).unwrap();
+    Ok(())
+}
+
 #[tauri::command]
 async fn init_process(window: Window, app_state: State<'_, Arc<Mutex<AppState>>>) -> Result<AppState, Error> {
     let state = app_state.lock().await;
@@ -156,6 +222,7 @@ fn main() {
         Ok(data) => {
             if let Some(config) = data {
                 state.config = config;
+                state.transport = Transport::from_config(&state.config);
                 state.adjust_installation_target();
             } else {
                 state.adjust_installation_target();
@@ -167,6 +234,17 @@ fn main() {
         }
     }
 
+    // Headless commands run without opening a window
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|a| a == "mirror") {
+        headless::attach_console();
+        std::process::exit(headless::run_mirror(state, &args[1..]));
+    }
+    if args.iter().any(|a| a == "--headless" || a == "--answers") {
+        headless::attach_console();
+        std::process::exit(headless::run(state, &args));
+    }
+
     let app_state = Arc::new(Mutex::new(state));
 
     tauri::Builder::default()
@@ -192,6 +270,9 @@ fn main() {
             select_component,
             unselect_component,
             installation_finished_back,
+            cancel_installation,
+            uninstall_component,
+            verify_installation,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
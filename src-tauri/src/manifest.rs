@@ -0,0 +1,220 @@
+use std::{collections::{BTreeMap, HashSet}, io::Read, path::{Path, PathBuf}};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// Relative to the installation path
+const MANIFEST_PATH: &str = ".bootstrapper/manifest.json";
+
+// Record of which files in an installation belong to which component
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct InstallManifest {
+    pub components: BTreeMap<String, ManifestComponent>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ManifestComponent {
+    pub date_modified: String,
+    pub hash: String,
+    pub channel: String,
+    #[serde(default)]
+    pub mirror: Option<String>,
+    // Preserved and merged paths, expected to be changed by the user
+    #[serde(default)]
+    pub protected_paths: Vec<String>,
+    pub files: Vec<ManifestFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ManifestFile {
+    // Relative to the installation path, always using forward slashes
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+// Files of an installed component that no longer match the manifest
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ComponentVerification {
+    pub id: String,
+    pub missing: Vec<String>,
+    pub modified: Vec<String>,
+    // Component is still in the component list, so it can be downloaded again
+    pub repairable: bool,
+}
+
+impl InstallManifest {
+    pub fn path(base_dir: &Path) -> PathBuf {
+        base_dir.join(MANIFEST_PATH)
+    }
+
+    pub fn load(base_dir: &Path) -> Result<Option<Self>, crate::Error> {
+        match std::fs::read_to_string(Self::path(base_dir)) {
+            Ok(data) => {
+                let manifest = serde_json::from_str(&data)
+                    .map_err(|e| crate::Error::GeneralError(format!("Installed manifest is invalid: {}", e)))?;
+                Ok(Some(manifest))
+            },
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    Err(e.into())
+                } else {
+                    Ok(None)
+                }
+            }
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, crate::Error> {
+        serde_json::to_string_pretty(self).map_err(|e| crate::Error::GeneralError(e.to_string()))
+    }
+
+    pub fn save(&self, base_dir: &Path) -> Result<(), crate::Error> {
+        let path = Self::path(base_dir);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    // Check every recorded file against what's on disk, returns only the components with problems
+    pub fn verify(&self, base_dir: &Path) -> Vec<ComponentVerification> {
+        let mut results = vec![];
+        for (id, comp) in self.components.iter() {
+            let mut missing = vec![];
+            let mut modified = vec![];
+            for file in comp.files.iter() {
+                let path = base_dir.join(&file.path);
+                match std::fs::metadata(&path) {
+                    Ok(metadata) if metadata.is_file() => {
+                        if is_preserved_path(&file.path, &comp.protected_paths) {
+                            continue;
+                        }
+                        // Size is cheap to check, only hash when it matches
+                        if metadata.len() != file.size {
+                            modified.push(file.path.clone());
+                        } else {
+                            match hash_file(&path) {
+                                Ok((_, sha256)) if sha256 == file.sha256 => (),
+                                _ => modified.push(file.path.clone()),
+                            }
+                        }
+                    },
+                    _ => missing.push(file.path.clone()),
+                }
+            }
+            if !missing.is_empty() || !modified.is_empty() {
+                results.push(ComponentVerification {
+                    id: id.clone(),
+                    missing,
+                    modified,
+                    repairable: false,
+                });
+            }
+        }
+        results
+    }
+
+    // Files the installed version of a component shipped that its new files don't include
+    pub fn orphaned_files(&self, id: &str, new_files: &[ManifestFile], preserved_paths: &[String]) -> Vec<String> {
+        let previous = match self.components.get(id) {
+            Some(previous) => previous,
+            None => return vec![],
+        };
+        let new_paths: HashSet<&str> = new_files.iter().map(|f| f.path.as_str()).collect();
+        let owned_by_others: HashSet<&str> = self.components.iter()
+            .filter(|(other_id, _)| other_id.as_str() != id)
+            .flat_map(|(_, c)| c.files.iter().map(|f| f.path.as_str()))
+            .collect();
+
+        previous.files.iter()
+            .map(|f| f.path.as_str())
+            .filter(|p| !new_paths.contains(p) && !owned_by_others.contains(p))
+            .filter(|p| !is_preserved_path(p, preserved_paths))
+            .map(|p| p.to_owned())
+            .collect()
+    }
+
+    // Delete the files owned by the given components, keeping any that another installed component also ships
+    pub fn remove_components(&mut self, base_dir: &Path, ids: &[String]) -> std::io::Result<()> {
+        let removed: Vec<ManifestComponent> = ids.iter()
+            .filter_map(|id| self.components.remove(id))
+            .collect();
+        let still_owned: HashSet<&str> = self.components.values()
+            .flat_map(|c| c.files.iter().map(|f| f.path.as_str()))
+            .collect();
+
+        for comp in removed.iter() {
+            for file in comp.files.iter() {
+                if still_owned.contains(file.path.as_str()) {
+                    continue;
+                }
+                let path = base_dir.join(&file.path);
+                match std::fs::remove_file(&path) {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+                    _ => (),
+                }
+                prune_empty_dirs(base_dir, &path);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Remove parent directories left empty, stopping at the installation path
+pub fn prune_empty_dirs(base_dir: &Path, path: &Path) {
+    let mut dir = path.parent();
+    while let Some(current) = dir {
+        if current == base_dir || !current.starts_with(base_dir) {
+            break;
+        }
+        // Only succeeds if the directory is empty
+        if std::fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+}
+
+impl ManifestFile {
+    pub fn from_path(base_dir: &Path, path: &Path) -> std::io::Result<Self> {
+        let relative_path = path.strip_prefix(base_dir)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        let (size, sha256) = hash_file(path)?;
+        Ok(ManifestFile {
+            path: manifest_path_string(relative_path),
+            size,
+            sha256,
+        })
+    }
+}
+
+// Preserved paths are relative to the installation path, and cover everything beneath a directory
+pub fn is_preserved_path(path: &str, preserved_paths: &[String]) -> bool {
+    preserved_paths.iter().any(|preserved| {
+        let preserved = preserved.replace('\\', "/");
+        let preserved = preserved.trim_matches('/');
+        !preserved.is_empty() && (path == preserved || path.starts_with(&format!("{}/", preserved)))
+    })
+}
+
+pub fn manifest_path_string(relative_path: &Path) -> String {
+    relative_path.to_string_lossy().replace('\\', "/")
+}
+
+pub fn hash_file(path: &Path) -> std::io::Result<(u64, String)> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+        size += read as u64;
+    }
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
@@ -0,0 +1,340 @@
+use std::{path::PathBuf, sync::{atomic::AtomicBool, Arc, Mutex}};
+
+use crate::{cache::DownloadCache, components::{get_all_components, nested_id, Component}, events::EventSink, installer::{download_component, partial_download_path, DownloadSources, DownloadState, InstallOutcome}, offline::COMPONENT_LIST_FILE, state::AppState, transaction::move_file, Error, OperatingSystem};
+
+pub const USAGE: &str = "Usage: fp-bootstrapper mirror --output <dir> [--channel <name>] [--target <windows|linux|macos>] [--select <id,id,...>] [--json]";
+
+// Headless download of a channel into a bundle directory usable as an offline source
+pub struct MirrorOptions {
+    pub output: PathBuf,
+    pub channel: Option<String>,
+    pub target: Option<OperatingSystem>,
+    // Component or category ids, everything in the channel if empty
+    pub select: Vec<String>,
+}
+
+impl MirrorOptions {
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut output = None;
+        let mut channel = None;
+        let mut target = None;
+        let mut select = vec![];
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().cloned().ok_or_else(|| format!("Missing value for {}", arg));
+            match arg.as_str() {
+                "--json" => (),
+                "--output" => output = Some(PathBuf::from(value()?)),
+                "--channel" => channel = Some(value()?),
+                "--target" => target = Some(value()?.parse()?),
+                "--select" => select.extend(value()?
+                    .split(',')
+                    .map(|id| id.trim().to_owned())
+                    .filter(|id| !id.is_empty())),
+                _ => return Err(format!("Unknown argument {}", arg)),
+            }
+        }
+
+        Ok(MirrorOptions {
+            output: output.ok_or_else(|| "Missing --output".to_owned())?,
+            channel,
+            target,
+            select,
+        })
+    }
+}
+
+pub async fn mirror_channel(mut state: AppState, options: MirrorOptions, events: Arc<dyn EventSink>) -> Result<(), Error> {
+    // Mirroring always downloads from the channel, never from another offline source
+    state.config.offline_source = None;
+    if let Some(target) = options.target {
+        state.installation_target = target;
+    }
+    let os_config = state.os_config()
+        .ok_or_else(|| Error::GeneralError("Selected platform does not have an installation candidate".to_owned()))?;
+    state.installation_channel = options.channel.unwrap_or(os_config.default_channel);
+
+    events.emit("stage", format!("Fetching component list for channel {}", state.installation_channel));
+    let data = state.load_component_list().await?;
+
+    let components: Vec<Component> = if options.select.is_empty() {
+        get_all_components(&state.components).into_iter().cloned().collect()
+    } else {
+        for id in options.select.iter() {
+            if state.components.find_dependencies(id).is_empty() {
+                return Err(Error::GeneralError(format!("Unknown component or category {}", id)));
+            }
+            state.components.select(id);
+        }
+        state.components.resolve_selection().into_iter().cloned().collect()
+    };
+
+    std::fs::create_dir_all(&options.output)?;
+    let sources = DownloadSources {
+        mirrors: state.mirror_urls(),
+        cache: DownloadCache::from_config(&state.config),
+        transport: state.transport.clone(),
+    };
+    let max_attempts = state.config.download_attempts.max(1);
+    let download_state = Arc::new(Mutex::new(DownloadState::default()));
+    let cancel_flag = AtomicBool::new(false);
+
+    for (i, comp) in components.iter().enumerate() {
+        events.emit("stage", format!("[{}/{}] Downloading {}", i + 1, components.len(), comp.id()));
+        download_state.lock().unwrap().start_component(comp);
+        let file = download_component(comp, &sources, max_attempts, events.as_ref(), &download_state, &cancel_flag)
+            .await
+            .map_err(|e| {
+                let message = format!("Failed to download {} - {}", comp.id(), e);
+                match InstallOutcome::from_error(e.as_ref()) {
+                    InstallOutcome::NetworkFailure => Error::NetworkError(message),
+                    InstallOutcome::IntegrityFailure => Error::HashMismatch(message),
+                    _ => Error::GeneralError(message),
+                }
+            })?;
+        drop(file);
+        // Download is already verified, move it into the bundle as is
+        move_file(&partial_download_path(comp), &options.output.join(format!("{}.zip", comp.id())))?;
+        download_state.lock().unwrap().finish_component(comp.id());
+    }
+
+    // Component zips sit next to the list, so downloads never leave the bundle
+    let data = if options.select.is_empty() {
+        data
+    } else {
+        let mirrored: Vec<String> = components.iter().map(|c| c.id().to_owned()).collect();
+        remove_unmirrored_components(&data, &mirrored)
+    };
+    std::fs::write(options.output.join(COMPONENT_LIST_FILE), rewrite_component_list(&data))?;
+    if !state.os_config().map(|c| c.public_keys).unwrap_or_default().is_empty() {
+        events.emit("warning", "The bundle's component list is not signed, offline installs will fail signature verification");
+    }
+    events.emit("stage", format!("Mirrored {} components to {:?}", components.len(), options.output));
+    Ok(())
+}
+
+// Point the list's url at its own directory and drop its mirrors, leaving everything else untouched
+fn rewrite_component_list(data: &str) -> String {
+    let mut data = remove_elements(data, "mirror");
+
+    if let Some((tag_start, tag_end)) = next_tag(&data, 0) {
+        let tag = &data[tag_start..tag_end - 1];
+        match attribute_range(tag, "url") {
+            Some(value) => data.replace_range(tag_start + value.start..tag_start + value.end, "./"),
+            None => {
+                let insert_at = if tag.ends_with('/') { tag_end - 2 } else { tag_end - 1 };
+                data.insert_str(insert_at, " url=\"./\"");
+            },
+        }
+    }
+    data
+}
+
+// Drop components that weren't mirrored, and categories left without any, so the bundle only lists what it holds
+fn remove_unmirrored_components(data: &str, mirrored: &[String]) -> String {
+    let mut removed: Vec<(usize, usize)> = vec![];
+    // Open categories, with their full id, where they start and whether anything in them is kept
+    let mut categories: Vec<(String, usize, bool)> = vec![];
+    let mut pos = 0;
+    while let Some((tag_start, tag_end)) = next_tag(data, pos) {
+        let tag = &data[tag_start + 1..tag_end - 1];
+        let self_closing = tag.ends_with('/');
+        pos = tag_end;
+
+        // Same ids ComponentList::setup gives them
+        let parent_id = categories.last().map(|(id, _, _)| id.as_str()).unwrap_or_default();
+        match element_name(tag) {
+            "category" if !self_closing => {
+                let id = nested_id(parent_id, attribute(tag, "id").unwrap_or_default());
+                categories.push((id, tag_start, false));
+            },
+            "/category" => {
+                if let Some((_, start, kept)) = categories.pop() {
+                    if kept {
+                        if let Some(parent) = categories.last_mut() {
+                            parent.2 = true;
+                        }
+                    } else {
+                        // Replaces anything removed inside it
+                        removed.retain(|(s, _)| *s < start);
+                        removed.push((start, pos));
+                    }
+                }
+            },
+            "component" => {
+                let id = nested_id(parent_id, attribute(tag, "id").unwrap_or_default());
+                let end = if self_closing { pos } else { element_end(data, pos, "component") };
+                if mirrored.contains(&id) {
+                    if let Some(parent) = categories.last_mut() {
+                        parent.2 = true;
+                    }
+                } else {
+                    removed.push((tag_start, end));
+                }
+                pos = end;
+            },
+            _ => (),
+        }
+    }
+    remove_ranges(data, &removed)
+}
+
+fn remove_elements(data: &str, name: &str) -> String {
+    let mut removed = vec![];
+    let mut pos = 0;
+    while let Some((tag_start, tag_end)) = next_tag(data, pos) {
+        let tag = &data[tag_start + 1..tag_end - 1];
+        pos = tag_end;
+        if element_name(tag) == name {
+            if !tag.ends_with('/') {
+                pos = element_end(data, pos, name);
+            }
+            removed.push((tag_start, pos));
+        }
+    }
+    remove_ranges(data, &removed)
+}
+
+// Start of the next element tag at or after from, and just past its '>'.
+// Comments, declarations and processing instructions are skipped, so a '>' inside them isn't mistaken for a tag
+fn next_tag(data: &str, from: usize) -> Option<(usize, usize)> {
+    let mut pos = from;
+    loop {
+        let start = pos + data[pos..].find('<')?;
+        let rest = &data[start..];
+        let skipped_until = if rest.starts_with("<!--") {
+            "-->"
+        } else if rest.starts_with("<![CDATA[") {
+            "]]>"
+        } else if rest.starts_with("<?") {
+            "?>"
+        } else if rest.starts_with("<!") {
+            ">"
+        } else {
+            return Some((start, start + rest.find('>')? + 1));
+        };
+        pos = start + rest.find(skipped_until)? + skipped_until.len();
+    }
+}
+
+// Just past the closing tag of an element whose opening tag ends at from
+fn element_end(data: &str, from: usize, name: &str) -> usize {
+    let close = format!("</{}>", name);
+    data[from..].find(&close).map(|end| from + end + close.len()).unwrap_or(data.len())
+}
+
+// Ranges must be in order and not overlap
+fn remove_ranges(data: &str, ranges: &[(usize, usize)]) -> String {
+    let mut result = String::with_capacity(data.len());
+    let mut copied = 0;
+    for &(start, end) in ranges {
+        // Take the indentation and line break with it, so no blank lines are left behind
+        let line_start = data[..start].trim_end_matches([' ', '\t']).len();
+        let start = if data[..line_start].ends_with('\n') { line_start - 1 } else { start };
+        let start = if data[..start].ends_with('\r') { start - 1 } else { start };
+        result.push_str(&data[copied..start.max(copied)]);
+        copied = end;
+    }
+    result + &data[copied..]
+}
+
+fn element_name(tag: &str) -> &str {
+    tag.split(|c: char| c.is_whitespace() || c == '>').next().unwrap_or_default().trim_end_matches('/')
+}
+
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    attribute_range(tag, name).map(|range| &tag[range])
+}
+
+// Where the attribute's value sits in the tag
+fn attribute_range(tag: &str, name: &str) -> Option<std::ops::Range<usize>> {
+    let pattern = format!("{}=\"", name);
+    let mut search_from = 0;
+    while let Some(offset) = tag[search_from..].find(&pattern) {
+        let start = search_from + offset;
+        // Must be the whole attribute name, not the end of a longer one
+        if tag[..start].ends_with(char::is_whitespace) {
+            let value_start = start + pattern.len();
+            return tag[value_start..].find('"').map(|len| value_start..value_start + len);
+        }
+        search_from = start + pattern.len();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::ComponentList;
+
+    const LIST: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!-- Published by <the build> -->
+<list>
+    <mirror>https://mirror.example/</mirror>
+    <mirror>https://other.example/</mirror>
+    <category id="core" name="Core" description="">
+        <component id="base" name="Base" description="" date-modified="" download-size="1" install-size="1" hash="00000000"/>
+        <!-- <component id="commented" /> -->
+        <category id="extras" name="Extras" description="">
+            <component id="music" name="Music" description="" date-modified="" download-size="1" install-size="1" hash="00000000">
+            </component>
+        </category>
+    </category>
+    <category id="games" name="Games" description="">
+        <component id="one" name="One" description="" date-modified="" download-size="1" install-size="1" hash="00000000"/>
+    </category>
+</list>
+"#;
+
+    fn component_ids(data: &str) -> Vec<String> {
+        let mut list: ComponentList = serde_xml_rs::from_str(data).unwrap();
+        list.setup();
+        let mut ids: Vec<String> = get_all_components(&list).iter().map(|c| c.id().to_owned()).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn rewrite_adds_url_to_root_and_drops_mirrors() {
+        let rewritten = rewrite_component_list(LIST);
+        let expected = LIST
+            .replace("<list>", r#"<list url="./">"#)
+            .replace("\n    <mirror>https://mirror.example/</mirror>\n    <mirror>https://other.example/</mirror>", "");
+        assert_eq!(rewritten, expected);
+    }
+
+    #[test]
+    fn rewrite_replaces_existing_url() {
+        let list = "<list url=\"https://example.com/list\" name=\"Test\">\n    <mirror url=\"https://mirror.example/\"/>\n</list>";
+        assert_eq!(rewrite_component_list(list), "<list url=\"./\" name=\"Test\">\n</list>");
+        assert_eq!(rewrite_component_list("<list/>"), "<list url=\"./\"/>");
+    }
+
+    #[test]
+    fn removes_components_and_emptied_categories() {
+        let mirrored = vec!["core-extras-music".to_owned()];
+        let filtered = remove_unmirrored_components(LIST, &mirrored);
+        assert_eq!(component_ids(&filtered), mirrored);
+        assert!(filtered.contains("<!-- <component id=\"commented\" /> -->"));
+        assert!(!filtered.contains("id=\"games\""));
+        assert!(!filtered.lines().any(|line| line.trim().is_empty()));
+    }
+
+    #[test]
+    fn removes_nested_category_with_nothing_mirrored() {
+        let mirrored = vec!["core-base".to_owned(), "games-one".to_owned()];
+        let filtered = remove_unmirrored_components(LIST, &mirrored);
+        assert_eq!(component_ids(&filtered), mirrored);
+        assert!(!filtered.contains("id=\"extras\""));
+        assert!(!filtered.contains("</component>"));
+        assert!(!filtered.lines().any(|line| line.trim().is_empty()));
+    }
+
+    #[test]
+    fn keeps_everything_mirrored() {
+        let mirrored = component_ids(LIST);
+        assert_eq!(mirrored, vec!["core-base", "core-extras-music", "games-one"]);
+        assert_eq!(remove_unmirrored_components(LIST, &mirrored), LIST);
+    }
+}
@@ -0,0 +1,88 @@
+use std::path::{Path, PathBuf};
+
+use reqwest::Url;
+use zip::ZipArchive;
+
+use crate::{staging::StagingDir, Error};
+
+// Bundles must have the component list at their root, next to the component zips
+pub const COMPONENT_LIST_FILE: &str = "components.xml";
+
+// Local path of a file:// url, None for anything else
+pub fn local_path(url: &str) -> Option<PathBuf> {
+    let url = Url::parse(url).ok()?;
+    if url.scheme() != "file" {
+        return None;
+    }
+    url.to_file_path().ok()
+}
+
+pub fn directory_url(dir: &Path) -> Result<String, Error> {
+    let absolute = std::env::current_dir()?.join(dir);
+    Url::from_directory_path(&absolute)
+        .map(|u| u.to_string())
+        .map_err(|_| Error::GeneralError(format!("Invalid offline source path {:?}", dir)))
+}
+
+// Directory holding the component list and zips, a bundle archive is extracted first into
+// a staging directory that's removed once dropped
+pub fn prepare_offline_source(source: &str, extract_parent: &Path) -> Result<(PathBuf, Option<StagingDir>), Error> {
+    let source = Path::new(source);
+    let (root, extracted) = if source.is_dir() {
+        (source.to_path_buf(), None)
+    } else if source.is_file() {
+        let extract_dir = StagingDir::create(extract_parent)?;
+        extract_bundle(source, extract_dir.path())?;
+        (extract_dir.path().to_path_buf(), Some(extract_dir))
+    } else {
+        return Err(Error::GeneralError(format!("Offline source {:?} does not exist", source)));
+    };
+
+    if !root.join(COMPONENT_LIST_FILE).is_file() {
+        return Err(Error::GeneralError(format!("Offline source {:?} does not contain {}", source, COMPONENT_LIST_FILE)));
+    }
+    Ok((root, extracted))
+}
+
+// Space a bundle archive takes up once extracted, nothing for a directory
+pub fn extracted_size(source: &str) -> Result<u64, Error> {
+    let source = Path::new(source);
+    if !source.is_file() {
+        return Ok(0);
+    }
+    let file = std::fs::File::open(source)?;
+    let mut archive = ZipArchive::new(file)
+        .map_err(|e| Error::GeneralError(format!("Invalid bundle archive {:?}: {}", source, e)))?;
+    let mut size = 0;
+    for i in 0..archive.len() {
+        let file = archive.by_index(i)
+            .map_err(|e| Error::GeneralError(format!("Invalid bundle archive {:?}: {}", source, e)))?;
+        size += file.size();
+    }
+    Ok(size)
+}
+
+fn extract_bundle(bundle: &Path, extract_dir: &Path) -> Result<(), Error> {
+    let file = std::fs::File::open(bundle)?;
+    let mut archive = ZipArchive::new(file)
+        .map_err(|e| Error::GeneralError(format!("Invalid bundle archive {:?}: {}", bundle, e)))?;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)
+            .map_err(|e| Error::GeneralError(format!("Invalid bundle archive {:?}: {}", bundle, e)))?;
+        let outpath = match file.enclosed_name() {
+            Some(path) => extract_dir.join(path),
+            None => continue,
+        };
+
+        if file.name().ends_with('/') {
+            std::fs::create_dir_all(&outpath)?;
+        } else {
+            if let Some(p) = outpath.parent() {
+                std::fs::create_dir_all(p)?;
+            }
+            let mut outfile = std::fs::File::create(&outpath)?;
+            std::io::copy(&mut file, &mut outfile)?;
+        }
+    }
+    Ok(())
+}
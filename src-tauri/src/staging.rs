@@ -0,0 +1,50 @@
+use std::{path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
+
+// Uniquely named directory components are extracted into before being moved into place,
+// removed when dropped so nothing is left behind however the install ends
+pub struct StagingDir {
+    path: PathBuf,
+}
+
+impl StagingDir {
+    pub fn create(parent: &Path) -> std::io::Result<Self> {
+        Self::create_named(parent, "staging")
+    }
+
+    pub fn create_named(parent: &Path, kind: &str) -> std::io::Result<Self> {
+        std::fs::create_dir_all(parent)?;
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+        let mut attempt = 0;
+        loop {
+            let path = parent.join(format!(".bootstrapper-{}-{}-{}-{}", kind, std::process::id(), nanos, attempt));
+            // Never reuse an existing directory, it might not be ours
+            match std::fs::create_dir(&path) {
+                Ok(()) => return Ok(StagingDir { path }),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => attempt += 1,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Leave the directory and its contents in place once this is dropped
+    pub fn keep(self) -> PathBuf {
+        let mut dir = std::mem::ManuallyDrop::new(self);
+        std::mem::take(&mut dir.path)
+    }
+
+    // Empty the directory between components
+    pub fn clear(&self) -> std::io::Result<()> {
+        std::fs::remove_dir_all(&self.path)?;
+        std::fs::create_dir(&self.path)
+    }
+}
+
+impl Drop for StagingDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
@@ -1,14 +1,11 @@
-use std::{collections::HashSet, path::Path, sync::{Arc, Mutex}, time::{Duration, Instant}};
+use std::{collections::{HashSet, VecDeque}, path::{Path, PathBuf}, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}};
 
-use crc32fast::Hasher;
 use serde::{Deserialize, Serialize};
-use tauri::{async_runtime::{spawn, JoinHandle}, Window};
-use futures::StreamExt;
-use tokio::io::AsyncWriteExt;
-use walkdir::WalkDir;
-use zip::ZipArchive;
+use tauri::async_runtime::{spawn, spawn_blocking, JoinHandle};
+use minisign_verify::{PublicKey, Signature};
+use tokio::sync::Semaphore;
 
-use crate::{config::AppConfig, Error, OperatingSystem, View};
+use crate::{cache::DownloadCache, components::{get_all_components, Component, ComponentList}, config::{AppConfig, OsConfig}, events::EventSink, installer::{download_component_queued, install_component, is_cancelled_error, partial_download_dir, partial_download_path, readable_byte_size, remove_orphaned_files, remove_partial_download, sort_mirrors_by_latency, DownloadSources, DownloadState, InstallOutcome, InstallationCancelled}, manifest::{ComponentVerification, InstallManifest, ManifestComponent}, fetch::Transport, offline::{directory_url, extracted_size, prepare_offline_source, COMPONENT_LIST_FILE}, staging::StagingDir, transaction::InstallTransaction, Error, OperatingSystem, View};
 
 // Store operating system name
 #[cfg(target_os = "windows")]
@@ -26,10 +23,26 @@ pub(crate) struct AppState {
     pub installation_target: OperatingSystem,
     pub installation_path: String,
     pub installation_channel: String,
+    // Installation path holds an existing installation being updated
+    pub updating: bool,
     pub components: ComponentList,
     pub config: AppConfig,
     #[serde(skip)]
-    pub task_handle: Arc<Mutex<Option<JoinHandle<()>>>>,
+    pub task_handle: Arc<Mutex<Option<JoinHandle<InstallOutcome>>>>,
+    #[serde(skip)]
+    pub cancel_flag: Arc<AtomicBool>,
+    // Directory holding the offline source's component list and zips
+    #[serde(skip)]
+    pub offline_root: Option<PathBuf>,
+    // Extracted bundle archive, removed when the installation using it ends
+    #[serde(skip)]
+    pub offline_bundle: Option<Arc<StagingDir>>,
+    // Install into a path with files in it that isn't a recognised installation, files it replaces are not kept once the install succeeds
+    #[serde(skip)]
+    pub overwrite_existing: bool,
+    // Fetches lists and components, other backends can be registered for their url scheme
+    #[serde(skip)]
+    pub transport: Transport,
 }
 
 impl Default for AppState {
@@ -41,243 +54,500 @@ impl Default for AppState {
             installation_target: OPERATING_SYSTEM,
             installation_path: "./Flashpoint".to_owned(),
             installation_channel: "Stable".to_owned(),
+            updating: false,
             components: ComponentList::default(),
             config: AppConfig::default(),
             task_handle: Arc::new(Mutex::new(None)),
+            cancel_flag: Arc::new(AtomicBool::new(false)),
+            offline_root: None,
+            offline_bundle: None,
+            overwrite_existing: false,
+            transport: Transport::default(),
         };
     }
 }
 
-fn remove_readonly_attr(path: &std::path::Path) -> std::io::Result<()> {
-    #[cfg(target_os = "windows")]
-    {
-        use std::os::windows::fs::MetadataExt;
-        let metadata = fs::metadata(path)?;
-        if metadata.file_attributes() & 0x1 != 0 { // FILE_ATTRIBUTE_READONLY
-            let mut options = OpenOptions::new();
-            options.write(true).custom_flags(0x80000000); // FILE_ATTRIBUTE_NORMAL
-            let _file = options.open(path)?;
-            // Opening the file with FILE_ATTRIBUTE_NORMAL is enough to clear the readonly attribute.
-        }
-    }
-    #[cfg(not(target_os = "windows"))]
-    {
-        use std::os::unix::fs::PermissionsExt;
-        let mut permissions = std::fs::metadata(path)?.permissions();
-        permissions.set_mode(0o644);
-        std::fs::set_permissions(path, permissions).unwrap();
-    }
-    Ok(())
-}
-
-pub async fn install_component(comp: &Component, base_url: &str, base_dir: &str, window: &Window, state: &mut DownloadState) -> Result<(), Box<dyn std::error::Error>> {
-    let mut temp_str = base_dir.to_owned() + "/Temp/";
-    let temp_str_cpy = temp_str.clone();
-    let temp_dir_path = Path::new(&temp_str_cpy);
-    if let Some(path) = &comp.path {
-        temp_str.push_str(path);
-    }
-    let base_dir_temp = Path::new(&temp_str);
-        
-    // Ensure base_dir temp exists
-    std::fs::create_dir_all(&base_dir_temp)?;
-
-    // Download each component zip to a file, then extract
-    let url = base_url.to_owned() + &comp.id + ".zip";
-    let file = download_file_tmp(&url, &comp.hash.to_uppercase(), window, state).await?;
-
-    // Extract to temp folder
-    let mut archive = ZipArchive::new(file)?;
-
-    state.stage = "Extracting".to_owned();
-    window.emit("download_state", state.clone()).unwrap();
-
-    for i in 0..archive.len() {
-        let mut file = archive.by_index(i)?;
-        let outpath = match file.enclosed_name() {
-            Some(path) => base_dir_temp.join(path),
-            None => continue,
-        };
-
-        if (&*file.name()).ends_with('/') {
-            // Create a directory if the file is a directory
-            std::fs::create_dir_all(&outpath)?;
-        } else {
-            // Ensure the file's parent directory exists
-            if let Some(p) = outpath.parent() {
-                if !p.exists() {
-                    std::fs::create_dir_all(&p)?;
+impl AppState {
+    pub fn adjust_installation_target(&mut self) {
+        match self.installation_target {
+            OperatingSystem::LINUX => {
+                if let Some(linux) = self.config.linux.as_ref() {
+                    self.installation_path = linux.default_path.clone();
+                } else {
+                    self.installation_target = OperatingSystem::WINDOWS;
                 }
             }
-
-            // Write the file content
-            let mut outfile = std::fs::File::create(&outpath)?;
-            std::io::copy(&mut file, &mut outfile)?;
+            OperatingSystem::MACOS => {
+                if let Some(macos) = self.config.macos.as_ref() {
+                    self.installation_path = macos.default_path.clone();
+                } else {
+                    self.installation_target = OperatingSystem::WINDOWS;
+                }
+            }
+            _ => {}
         }
     }
 
-    // Move files from Temp to main dir
+    // Remove an installed component, and everything that depends on it only when cascade is set, returns the removed ids
+    pub fn uninstall_component(&mut self, id: &str, cascade: bool) -> Result<Vec<String>, Error> {
+        if matches!(self.view, View::INSTALLATION) {
+            return Err(Error::GeneralError("Cannot uninstall while an installation is running".to_owned()));
+        }
 
-    for entry in WalkDir::new(temp_dir_path).into_iter().filter_map(|e| e.ok()) {
-        let path = entry.path();
-        let relative_path = path.strip_prefix(temp_dir_path)?;
-        let dest_path = Path::new(base_dir).join(relative_path);
+        // Required components, and anything they depend on, have to stay
+        let is_required = self.components.required.iter()
+            .any(|r| r == id || self.components.find_dependencies(r).iter().any(|d| d == id));
+        if is_required {
+            return Err(Error::GeneralError(format!("{} is required and cannot be uninstalled", id)));
+        }
 
-        if path.is_dir() {
-            std::fs::create_dir_all(&dest_path)?;
-        } else if path.is_file() {
-            let _ = remove_readonly_attr(&dest_path); // If it fails, the copy error will present itself soon and bubble up later
-            std::fs::copy(path, &dest_path)?;
+        let base_dir = Path::new(&self.installation_path);
+        let mut manifest = InstallManifest::load(base_dir)?
+            .ok_or_else(|| Error::GeneralError("No installed components found at the installation path".to_owned()))?;
+
+        // Dependants can't work without it, so they have to go too
+        let to_remove: Vec<String> = self.components.find_dependants(id)
+            .into_iter()
+            .filter(|d| manifest.components.contains_key(d))
+            .collect();
+        if to_remove.is_empty() {
+            return Err(Error::GeneralError(format!("{} is not installed", id)));
         }
+        let dependants: Vec<&String> = to_remove.iter().filter(|d| *d != id).collect();
+        if !cascade && !dependants.is_empty() {
+            let dependants: Vec<&str> = dependants.iter().map(|d| d.as_str()).collect();
+            return Err(Error::GeneralError(format!("{} is needed by installed components {}, they must be uninstalled too", id, dependants.join(", "))));
+        }
+
+        manifest.remove_components(base_dir, &to_remove)?;
+        manifest.save(base_dir)?;
+
+        self.components.selected.retain(|s| !to_remove.contains(s));
+        self.components.updates.retain(|s| !to_remove.contains(s));
+        self.components.repairs.retain(|s| !to_remove.contains(s));
+        let installed: Vec<String> = manifest.components.keys().cloned().collect();
+        self.components.mark_installed(&installed);
+        Ok(to_remove)
     }
 
-    std::fs::remove_dir_all(temp_dir_path)?;
-    std::fs::create_dir_all(temp_dir_path)?;
-    
-    Ok(())
-}
+    // Check installed files against the manifest and queue damaged components to be installed again
+    pub async fn verify_installation(&mut self) -> Result<Vec<ComponentVerification>, Error> {
+        if !self.updating || !matches!(self.view, View::SETUPSELECT) {
+            return Err(Error::GeneralError("Select an existing installation to verify".to_owned()));
+        }
 
-pub async fn download_file_tmp(url: &str, crc32_hash: &str, window: &Window, state: &mut DownloadState) -> Result<std::fs::File, Box<dyn std::error::Error>> {
-    let mut tmp_file = tokio::fs::File::from(tempfile::tempfile()?);
-    let mut byte_stream = reqwest::get(url).await?.bytes_stream();
-    let mut hasher = Hasher::new();
-    let mut last_call = Instant::now();
-
-    while let Some(item) = byte_stream.next().await {
-        let chunk = item?;
-        state.total_downloaded += chunk.len() as u64;
-        if last_call.elapsed() >= Duration::from_millis(200) {
-            window.emit("download_state", state.clone()).unwrap();
-            last_call = Instant::now();
+        let base_dir = PathBuf::from(&self.installation_path);
+        let manifest = InstallManifest::load(&base_dir)?
+            .ok_or_else(|| Error::GeneralError("No installed components found at the installation path".to_owned()))?;
+        // Hashing every file takes a while, keep it off the async runtime
+        let mut report = spawn_blocking(move || manifest.verify(&base_dir))
+            .await
+            .map_err(|e| Error::GeneralError(e.to_string()))?;
+
+        let known_ids: HashSet<String> = get_all_components(&self.components).iter().map(|c| c.id.clone()).collect();
+        for result in report.iter_mut() {
+            result.repairable = known_ids.contains(&result.id);
+        }
+        self.components.repairs = report.iter()
+            .filter(|r| r.repairable)
+            .map(|r| r.id.clone())
+            .collect();
+        for id in self.components.repairs.clone() {
+            self.components.select(&id);
         }
-        
-        hasher.update(&chunk);
-        tmp_file.write_all(&chunk).await?;
+        Ok(report)
     }
-    tmp_file.flush().await?;
-
-    if crc32_hash != "00000000" {
-        let calculated_hash = hasher.finalize();
-        let calculated_hash_str = format!("{:08x}", calculated_hash);
-        if calculated_hash_str.to_uppercase() != crc32_hash {
-            let msg = format!("Download failed, hash mismatch: Got {:?} expected {:?} - URL: {:?}", calculated_hash_str.to_uppercase(), crc32_hash, url);
-            return Err(Box::new(crate::Error::GeneralError(msg)));
+
+    // Refuse to install components that can only be verified by CRC32
+    pub fn check_strong_hashes(&self) -> Result<(), Error> {
+        let missing: Vec<String> = self.components.resolve_selection()
+            .into_iter()
+            .filter(|c| c.sha256.as_deref().map(str::trim).unwrap_or_default().is_empty())
+            .map(|c| c.id.clone())
+            .collect();
+        if !missing.is_empty() {
+            return Err(Error::GeneralError(format!("Components without a SHA-256 hash cannot be installed: {}", missing.join(", "))));
         }
+        Ok(())
     }
 
-    let t = tmp_file.try_into_std();
-    Ok(t.map_err(|_| crate::Error::GeneralError("Unknown".to_owned()))?)
-}
+    // Make sure the installation and temp volumes can hold everything before starting
+    pub fn check_free_space(&self) -> Result<(), Error> {
+        let components = self.components.resolve_selection();
+        let install_size: u64 = components.iter().map(|c| c.install_size).sum();
+        // Each component is extracted to a staging folder before being moved into place
+        let staging_size = components.iter().map(|c| c.install_size).max().unwrap_or(0);
+        // Downloads are kept until extracted, at most one per download slot
+        let mut download_sizes: Vec<u64> = components.iter().map(|c| c.download_size).collect();
+        download_sizes.sort_unstable_by(|a, b| b.cmp(a));
+        let download_size: u64 = download_sizes.iter()
+            .take(self.config.max_concurrent_downloads.max(1))
+            .sum();
+
+        let install_dir = existing_ancestor(Path::new(&self.installation_path))?;
+        let staging_dir = match self.config.staging_dir.as_ref() {
+            Some(dir) => existing_ancestor(Path::new(dir))?,
+            None => install_dir.clone(),
+        };
+        let temp_dir = existing_ancestor(&partial_download_dir())?;
+        check_space(&[(install_dir, install_size), (staging_dir, staging_size), (temp_dir, download_size)])
+    }
 
-impl AppState {
-    pub fn adjust_installation_target(&mut self) {
+    pub fn os_config(&self) -> Option<OsConfig> {
         match self.installation_target {
-            OperatingSystem::LINUX => {
-                if let Some(linux) = self.config.linux.as_ref() {
-                    self.installation_path = linux.default_path.clone();
-                } else {
-                    self.installation_target = OperatingSystem::WINDOWS;
-                }
+            OperatingSystem::LINUX => self.config.linux.clone(),
+            OperatingSystem::WINDOWS => self.config.windows.clone(),
+            OperatingSystem::MACOS => self.config.macos.clone(),
+        }
+    }
+
+    // Base urls to download components from, the component list's own url first
+    pub fn mirror_urls(&self) -> Vec<String> {
+        // Offline installs only ever read from the local source
+        if let Some(root) = self.offline_root.as_ref() {
+            return directory_url(root).into_iter().collect();
+        }
+        let mut mirrors: Vec<String> = vec![];
+        let os_mirrors = self.os_config().map(|c| c.mirrors).unwrap_or_default();
+        let candidates = std::iter::once(&self.components.url)
+            .chain(self.components.mirrors.iter())
+            .chain(os_mirrors.iter());
+        for url in candidates {
+            let url = url.trim();
+            if url.is_empty() {
+                continue;
             }
-            OperatingSystem::MACOS => {
-                if let Some(macos) = self.config.macos.as_ref() {
-                    self.installation_path = macos.default_path.clone();
-                } else {
-                    self.installation_target = OperatingSystem::WINDOWS;
-                }
+            let mut url = url.to_owned();
+            if !url.ends_with('/') {
+                url.push('/');
+            }
+            if !mirrors.contains(&url) {
+                mirrors.push(url);
             }
-            _ => {}
         }
+        mirrors
     }
 
-    pub async fn start_downloader(&mut self, window: tauri::Window) {
-        let components_ref = get_all_components(&self.components);
-        let components: Vec<Component> = components_ref.iter().map(|c| (*c).clone()).collect();
-        let base_url = self.components.url.clone();
+    pub async fn start_downloader(&mut self, events: Arc<dyn EventSink>) {
+        let components: Vec<Component> = self.components.resolve_selection().into_iter().cloned().collect();
+        let mirrors = self.mirror_urls();
+        // Dropped with the task, so an extracted bundle doesn't outlive the install
+        let offline_bundle = self.offline_bundle.take();
+        let probe_mirrors = self.config.probe_mirrors && mirrors.len() > 1;
         let base_dir = self.installation_path.clone();
+        let channel = self.installation_channel.clone();
+        let preserved_paths = self.config.preserved_paths.clone();
+        let cancel_flag = self.cancel_flag.clone();
+        cancel_flag.store(false, Ordering::SeqCst);
+        let max_downloads = self.config.max_concurrent_downloads.max(1);
+        let max_attempts = self.config.download_attempts.max(1);
+        let cache = DownloadCache::from_config(&self.config);
+        let transport = self.transport.clone();
+        let staging_parent = self.config.staging_dir.as_ref().map(PathBuf::from);
 
         let mut handle = self.task_handle.lock().unwrap(); // Lock the handle in state
 
         *handle = Some(spawn(async move {
             let mut download_state = DownloadState::default();
+            let mut installed: Vec<String> = vec![];
 
             download_state.total_components = components.len();
             download_state.component_number = 0;
             download_state.total_size = components.iter().map(|c| c.download_size).sum();
-
-            for comp in components {
-                download_state.component_number += 1;
-                download_state.current = Some(comp.clone());
-                download_state.stage = "Downloading".to_owned();
-                window.emit("download_state", download_state.clone()).unwrap();
-                match install_component(&comp, &base_url, &base_dir, &window, &mut download_state).await {
-                    Ok(_) => (),
+            download_state.stage = "Downloading".to_owned();
+            events.emit("download_state", download_state.clone());
+            let download_state = Arc::new(Mutex::new(download_state));
+
+            let mut transaction = match InstallTransaction::begin(Path::new(&base_dir), staging_parent.as_deref()) {
+                Ok(transaction) => transaction,
+                Err(e) => {
+                    events.emit("fatal_error", format!("Failed to prepare installation path - {:?}", e.to_string()));
+                    return InstallOutcome::Failed;
+                },
+            };
+
+            // Add to the manifest of any existing installation
+            let mut manifest = match InstallManifest::load(Path::new(&base_dir)) {
+                Ok(manifest) => manifest.unwrap_or_default(),
+                Err(e) => {
+                    events.emit("fatal_error", e.to_string());
+                    return InstallOutcome::Failed;
+                },
+            };
+
+            let sources = DownloadSources {
+                mirrors: if probe_mirrors {
+                    sort_mirrors_by_latency(mirrors).await
+                } else {
+                    mirrors
+                },
+                cache,
+                transport,
+            };
+
+            // The semaphore limits how many downloads run at once, slots are only freed once extracted
+            let semaphore = Arc::new(Semaphore::new(max_downloads));
+            let mut queued: VecDeque<&Component> = components.iter().collect();
+            let mut pending = VecDeque::new();
+
+            // Extract one at a time, in list order, as downloads complete
+            loop {
+                // Start downloads in list order, so the next one to extract always holds a slot
+                while !queued.is_empty() {
+                    let permit = match semaphore.clone().try_acquire_owned() {
+                        Ok(permit) => permit,
+                        Err(_) => break,
+                    };
+                    let comp = queued.pop_front().unwrap();
+                    let download = spawn(download_component_queued(
+                        comp.clone(),
+                        sources.clone(),
+                        max_attempts,
+                        events.clone(),
+                        download_state.clone(),
+                        permit,
+                        cancel_flag.clone(),
+                    ));
+                    pending.push_back((comp, download));
+                }
+                let (comp, download) = match pending.pop_front() {
+                    Some(next) => next,
+                    None => break,
+                };
+                let res = match download.await {
+                    Ok(Ok((file, _permit))) => {
+                        if pending.is_empty() && queued.is_empty() {
+                            let mut state = download_state.lock().unwrap();
+                            state.stage = "Extracting".to_owned();
+                        }
+                        // Extraction blocks, so keep it off the runtime where downloads and progress events run
+                        let previous = manifest.components.get(&comp.id).cloned();
+                        let (extract_comp, extract_events, extract_state, extract_cancel) = (comp.clone(), events.clone(), download_state.clone(), cancel_flag.clone());
+                        let extraction = spawn_blocking(move || {
+                            let res = install_component(&extract_comp, file, previous.as_ref(), &mut transaction, extract_events.as_ref(), &extract_state, &extract_cancel);
+                            (transaction, res)
+                        }).await;
+                        match extraction {
+                            Ok((returned, res)) => {
+                                transaction = returned;
+                                res.and_then(|files| {
+                                    remove_orphaned_files(comp, &files, &manifest, &base_dir, &preserved_paths, &mut transaction)?;
+                                    Ok(files)
+                                })
+                            },
+                            Err(e) => {
+                                // The transaction went down with the extraction, nothing left to roll back with
+                                for (_, download) in pending.drain(..) {
+                                    download.abort();
+                                    let _ = download.await;
+                                }
+                                events.emit("fatal_error", format!("Extraction of {:?} stopped unexpectedly, the installation could not be rolled back - {:?}", comp.id, e.to_string()));
+                                return InstallOutcome::Failed;
+                            },
+                        }
+                    },
+                    Ok(Err(e)) => Err(e),
+                    Err(e) => Err(Error::GeneralError(e.to_string()).into()),
+                };
+                if res.is_err() {
+                    // Stop any downloads still running before cleaning up
+                    for (_, download) in pending.drain(..) {
+                        download.abort();
+                        let _ = download.await;
+                    }
+                }
+                match res {
+                    Ok(files) => {
+                        installed.push(comp.id.clone());
+                        let mut state = download_state.lock().unwrap();
+                        manifest.components.insert(comp.id.clone(), ManifestComponent {
+                            date_modified: comp.date_modified.clone(),
+                            hash: comp.hash.clone(),
+                            channel: channel.clone(),
+                            mirror: state.mirrors.get(&comp.id).cloned(),
+                            protected_paths: comp.protected_paths(),
+                            files,
+                        });
+                        state.finish_component(&comp.id);
+                        events.emit("download_state", state.clone());
+                    },
                     Err(e) => {
-                        window.emit("fatal_error", format!("During Install of {:?} - {:?}", comp.id, e.to_string())).unwrap();
-                        return;
+                        let cancelled = is_cancelled_error(e.as_ref());
+                        if cancelled {
+                            // Failed downloads are kept to resume later, cancelled ones are not
+                            for comp in components.iter() {
+                                remove_partial_download(&partial_download_path(comp));
+                            }
+                        }
+
+                        // Put the installation path back how it was before the run started
+                        let rollback = transaction.rollback();
+                        let rolled_back = rollback.is_ok();
+
+                        if cancelled {
+                            if let Err(rollback_err) = rollback {
+                                events.emit("fatal_error", format!("Failed to roll back installation - {:?}", rollback_err.to_string()));
+                            }
+                            let (installed, rolled_back) = if rolled_back {
+                                (vec![], installed)
+                            } else {
+                                (installed, vec![])
+                            };
+                            let not_installed = components.iter()
+                                .filter(|c| !installed.contains(&c.id))
+                                .map(|c| c.id.clone())
+                                .collect();
+                            events.emit("installation_cancelled", InstallationCancelled {
+                                installed,
+                                not_installed,
+                                rolled_back,
+                            });
+                        } else {
+                            let mut message = format!("During Install of {:?} - {:?}", comp.id, e.to_string());
+                            if let Err(rollback_err) = rollback {
+                                message += &format!(", failed to roll back installation - {:?}", rollback_err.to_string());
+                            }
+                            events.emit("fatal_error", message);
+                        }
+                        return InstallOutcome::from_error(e.as_ref());
                     },
                 }
             }
-            window.emit("installation_finished", 0).unwrap();
+            let manifest_written = manifest.to_json()
+                .map_err(|e| e.to_string())
+                .and_then(|data| {
+                    transaction.write_file(&InstallManifest::path(Path::new(&base_dir)), data.as_bytes())
+                        .map_err(|e| e.to_string())
+                });
+            if let Err(e) = manifest_written {
+                let mut message = format!("Failed to write installation manifest - {:?}", e);
+                if let Err(rollback_err) = transaction.rollback() {
+                    message += &format!(", failed to roll back installation - {:?}", rollback_err.to_string());
+                }
+                events.emit("fatal_error", message);
+                return InstallOutcome::Failed;
+            }
+            if let Err(e) = transaction.commit() {
+                events.emit("fatal_error", format!("Failed to clean up installation backups - {:?}", e.to_string()));
+                return InstallOutcome::Failed;
+            }
+            events.emit("installation_finished", 0);
+            drop(offline_bundle);
+            InstallOutcome::Finished
         }));
 
         drop(handle); // Drop the lock
     }
 
-    pub async fn change_view(&mut self, view: View, window: tauri::Window) -> Result<(), Error> {
+    // Tell the installer to stop, the returned handle finishes once it has cleaned up after itself
+    pub fn request_cancel(&self) -> Option<JoinHandle<InstallOutcome>> {
+        self.cancel_flag.store(true, Ordering::SeqCst);
+        self.task_handle.lock().unwrap().take()
+    }
+
+    pub async fn cancel_installation(&mut self) -> Result<(), Error> {
+        // Wait for the installer to reach a safe point and clean up after itself
+        if let Some(handle) = self.request_cancel() {
+            handle.await.map_err(|e| Error::GeneralError(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    // Download, verify and parse the selected channel's component list, returns the list as downloaded
+    pub async fn load_component_list(&mut self) -> Result<String, Error> {
+        // Find the correct source url
+        let os_config_opt = self.os_config();
+
+        if os_config_opt.is_none() {
+            return Err(Error::GeneralError(
+                "Selected platform does not have an installation candidate".to_owned(),
+            ));
+        }
+        let os_config = os_config_opt.unwrap();
+
+        // Remove any bundle extracted for an earlier list before extracting it again
+        self.offline_bundle = None;
+        self.offline_root = match self.config.offline_source.clone() {
+            Some(source) => {
+                let extract_parent = partial_download_dir();
+                // Bundles can be large, keep extraction off the async runtime
+                let (root, extracted) = spawn_blocking(move || {
+                    check_space(&[(existing_ancestor(&extract_parent)?, extracted_size(&source)?)])?;
+                    prepare_offline_source(&source, &extract_parent)
+                })
+                    .await
+                    .map_err(|e| Error::GeneralError(e.to_string()))??;
+                self.offline_bundle = extracted.map(Arc::new);
+                Some(root)
+            },
+            None => None,
+        };
+        let xml_url = match self.offline_root.as_ref() {
+            Some(root) => directory_url(root)? + COMPONENT_LIST_FILE,
+            None => os_config.channels.get(&self.installation_channel)
+                .cloned()
+                .unwrap_or_default(),
+        };
+
+        // If no source url found, channel does not exist
+        if xml_url.is_empty() {
+            return Err(Error::GeneralError(
+                "Selected channel does not exist".to_owned(),
+            ));
+        }
+        let data = self.transport.bytes(&xml_url)
+            .await
+            .map_err(|e| Error::NetworkError(e.to_string()))?;
+        // Only trust lists signed by a configured key, before parsing anything
+        if !os_config.public_keys.is_empty() {
+            let signature = self.transport.text(&(xml_url.clone() + ".minisig"))
+                .await
+                .map_err(|e| Error::SignatureError(format!("could not download signature: {}", e)))?;
+            verify_component_list(&data, &signature, &os_config.public_keys)?;
+        }
+        let data = String::from_utf8(data)
+            .map_err(|e| Error::GeneralError(e.to_string()))?;
+        let mut comp: ComponentList = serde_xml_rs::from_str(&data)
+            .map_err(|e| Error::GeneralError(e.to_string()))?;
+        // Calculate required values and mark as selected
+        comp.setup();
+        // Bundles and mirrors of the list may use urls relative to it
+        comp.resolve_urls(&xml_url);
+        self.components = comp;
+        Ok(data)
+    }
+
+    pub async fn change_view(&mut self, view: View, events: Arc<dyn EventSink>) -> Result<(), Error> {
         match self.view {
             View::SETUP => {
                 match view {
                     View::SETUPSELECT => {
-                        // Validate path
-                        println!("Checking {:?}", &self.installation_path);
-                        let is_empty = installation_path_is_safe(&self.installation_path)?;
-                        if !is_empty {
-                            // Try appending Flashpoint as a subdirectory
-                            let new_path =
-                                Path::join(Path::new(&self.installation_path), "Flashpoint");
-                            let is_new_empty =
-                                installation_path_is_safe(&new_path.to_string_lossy())?;
-                            if !is_new_empty {
-                                return Err(Error::GeneralError("Installation path already contains files or a Flashpoint directory".to_owned()));
-                            } else {
+                        // Validate path, an existing installation we recognise is opened for updating
+                        eprintln!("Checking {:?}", &self.installation_path);
+                        let mut existing_manifest = InstallManifest::load(Path::new(&self.installation_path))?;
+                        if existing_manifest.is_none() && !self.overwrite_existing {
+                            let is_empty = installation_path_is_safe(&self.installation_path)?;
+                            if !is_empty {
+                                // Try appending Flashpoint as a subdirectory
+                                let new_path =
+                                    Path::join(Path::new(&self.installation_path), "Flashpoint");
+                                existing_manifest = InstallManifest::load(&new_path)?;
+                                if existing_manifest.is_none() {
+                                    let is_new_empty =
+                                        installation_path_is_safe(&new_path.to_string_lossy())?;
+                                    if !is_new_empty {
+                                        return Err(Error::GeneralError("Installation path already contains files or a Flashpoint directory".to_owned()));
+                                    }
+                                }
                                 // Save valid modified path to state
                                 self.installation_path = new_path.to_string_lossy().to_string();
                             }
                         }
+                        self.updating = existing_manifest.is_some();
 
-                        // Find the correct source url
-                        let os_config_opt = match self.installation_target {
-                            OperatingSystem::LINUX => self.config.linux.clone(),
-                            OperatingSystem::WINDOWS => self.config.windows.clone(),
-                            OperatingSystem::MACOS => self.config.macos.clone(),
-                        };
-
-                        if os_config_opt.is_none() {
-                            return Err(Error::GeneralError(
-                                "Selected platform does not have an installation candidate".to_owned(),
-                            ));
-                        }
-                        let os_config = os_config_opt.unwrap();
-
-                        let xml_url = os_config.channels.get(&self.installation_channel)
-                            .cloned()
-                            .unwrap_or_default();
-
-                        // If no source url found, channel does not exist
-                        if xml_url.is_empty() {
-                            return Err(Error::GeneralError(
-                                "Selected channel does not exist".to_owned(),
-                            ));
-                        } else {
-                            let data = download_text_file(&xml_url)
-                                .await
-                                .map_err(|e| Error::GeneralError(e.to_string()))?;
-                            let mut comp: ComponentList = serde_xml_rs::from_str(&data)
-                                .map_err(|e| Error::GeneralError(e.to_string()))?;
-                            // Calculate required values and mark as selected
-                            comp.setup();
-                            self.components = comp;
+                        self.load_component_list().await?;
+                        if let Some(manifest) = existing_manifest.as_ref() {
+                            self.components.apply_manifest(manifest);
                         }
                     }
                     _ => {
@@ -289,7 +559,11 @@ impl AppState {
                 match view {
                     View::SETUP => (),
                     View::INSTALLATION => {
-                        self.start_downloader(window).await;
+                        if self.config.require_strong_hash {
+                            self.check_strong_hashes()?;
+                        }
+                        self.check_free_space()?;
+                        self.start_downloader(events).await;
                     },
                     _ => {
                         return Err(crate::Error::GeneralError("Invalid view transition".to_owned()));
@@ -299,6 +573,9 @@ impl AppState {
             View::INSTALLATION => {
                 match view {
                     View::FINISHED => (),
+                    View::ABORTED => {
+                        self.cancel_installation().await?;
+                    },
                     _ => {
                         return Err(crate::Error::GeneralError("Invalid view transition".to_owned()));
                     }
@@ -313,372 +590,86 @@ impl AppState {
     }
 }
 
-async fn download_text_file(url: &str) -> Result<String, reqwest::Error> {
-    let resp = reqwest::get(url).await?;
-    Ok(resp.text().await?)
-}
-
-fn installation_path_is_safe(dir: &str) -> std::io::Result<bool> {
-    match std::fs::read_dir(dir) {
-        Ok(mut entries) => Ok(!entries.next().is_some()),
-        Err(e) => {
-            if e.kind() == std::io::ErrorKind::NotFound {
-                // Bad installation path, alert and return early
-                Ok(true)
-            } else {
-                Err(e)
-            }
+// Group what's needed per volume, then make sure each has room for it
+fn check_space(requirements: &[(PathBuf, u64)]) -> Result<(), Error> {
+    let mut volumes: Vec<(PathBuf, u64)> = vec![];
+    for (dir, needed) in requirements {
+        match volumes.iter_mut().find(|(other, _)| same_volume(other, dir)) {
+            Some(volume) => volume.1 += needed,
+            None => volumes.push((dir.clone(), *needed)),
         }
     }
-}
-
-fn deserialize_bool<'de, D>(deserializer: D) -> Result<bool, D::Error>
-where
-    D: serde::Deserializer<'de>,
-{
-    let s: String = Deserialize::deserialize(deserializer)?;
 
-    match s.as_str() {
-        "1" => Ok(true),
-        "0" => Ok(false),
-        _ => Ok(false),
-    }
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct ComponentList {
-    #[serde(rename = "url", default)]
-    url: String,
-    #[serde(rename = "categories", alias = "category", default)]
-    categories: Vec<Category>,
-    #[serde(default)]
-    pub selected: Vec<String>,
-    #[serde(default)]
-    pub required: Vec<String>,
-}
-
-impl Default for ComponentList {
-    fn default() -> Self {
-        ComponentList {
-            url: "Example Component List".to_owned(),
-            categories: vec![],
-            selected: vec![],
-            required: vec![],
+    let mut shortfalls = vec![];
+    for (dir, needed) in volumes {
+        let available = fs4::available_space(&dir)?;
+        if available < needed {
+            shortfalls.push(format!(
+                "{} needs {} but only {} is free ({} short)",
+                dir.display(),
+                readable_byte_size(needed),
+                readable_byte_size(available),
+                readable_byte_size(needed - available),
+            ));
         }
     }
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct Category {
-    id: String,
-    #[serde(alias = "title")]
-    name: String,
-    description: String,
-    // This field can either be a nested category or a component. Depending on your XML structure and needs, you might need to adjust the handling.
-    #[serde(alias = "category", default)]
-    subcategories: Vec<Category>,
-    #[serde(alias = "component")]
-    components: Vec<Component>,
-    #[serde(default)]
-    required: bool,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Component {
-    id: String,
-    #[serde(alias = "title")]
-    name: String,
-    description: String,
-    #[serde(alias = "date-modified")]
-    date_modified: String,
-    #[serde(alias = "download-size")]
-    download_size: u64,
-    #[serde(alias = "install-size")]
-    install_size: u64,
-    path: Option<String>,
-    hash: String,
-    depends: Option<String>,
-    #[serde(default, deserialize_with = "deserialize_bool")]
-    required: bool,
-    #[serde(default)]
-    installed: bool,
-}
-
-fn update_ids_in_category(
-    category: &mut Category,
-    working_id: &str,
-) {
-    // Update own ID
-    let mut new_working_id = working_id.to_owned() + "-" + &category.id;
-    if new_working_id.starts_with("-") {
-        new_working_id.remove(0);
-    }
-    category.id = new_working_id.clone();
-
-    for subcat in &mut category.subcategories {
-        update_ids_in_category(subcat, &new_working_id);
-    }
-
-    // Update all components
-    for comp in &mut category.components {
-        comp.id = new_working_id.clone() + "-" + &comp.id;
+    if !shortfalls.is_empty() {
+        return Err(Error::GeneralError(format!("Not enough disk space - {}", shortfalls.join(", "))));
     }
+    Ok(())
 }
 
-impl ComponentList {
-    pub fn setup(&mut self) {
-        let mut required = vec![];
-        for category in &mut self.categories {
-            // Update all IDs to be correct inside category
-            update_ids_in_category(category, ""); 
-        }
-        for category in self.categories.iter() {
-            // Add to the list of required components and categories
-            self.find_required(category, &mut required);
-        }
-        required.sort();
-        required.dedup();
-        self.required = required.clone();
-    }
-
-    fn find_required(
-        &self,
-        category: &Category,
-        list: &mut Vec<String>
-    ) -> bool {
-        let mut is_required = true;
-        // Find non-required comps
-        for component in category.components.iter() {
-            if component.required {
-                // Add all dependants
-                let mut dependencies = self.find_dependencies(&component.id);
-                list.append(&mut dependencies);
-            } else {
-                is_required = false;
-            }
-        }
-        for subcat in category.subcategories.iter() {
-            if subcat.required {
-                // If required itself, add all child components
-                let mut subcat_comps = vec![];
-                collect_category_components(subcat, &mut subcat_comps);
-                for comp in subcat_comps {
-                    list.push(comp.id.clone());
-                }
-                list.push(subcat.id.clone());
-            } else {
-                // If the category isn't stated required, then we'll decide by checking if all children are required instead
-                let required = self.find_required(subcat, list);
-                if !required {
-                    is_required = false;
-                }
-            }
-     
-        }
-        // Finally can add to list
-        if is_required {
-            list.push(category.id.clone());
-        }
-        is_required
-    }    
-
-    // pub fn mark_required(&mut self, id: &str) {
-    //     let mut required = vec![];
-    //     let components = get_all_components(&self);
-
-    //     if components.iter().find(|&c| c.id == id).is_some() {
-    //         // Is a component
-    //         self.find_dependants_recursive(id, &components, &mut required);
-    //         required.push(id.to_owned());
-    //     } else if let Some(category) = self.find_category_by_id(id) {
-    //         // Is a category
-    //         let mut category_components = vec![];
-    //         collect_category_components(category, &mut category_components);
-    //         for comp in category_components.iter() {
-    //             self.find_dependants_recursive(&comp.id, &components, &mut required);
-    //             required.push(comp.id.clone());
-    //         }
-    //     }
-
-    //     self.required.append(&mut required);
-    //     self.required.sort();
-    //     self.required.dedup();
-    // }
-
-    pub fn select(&mut self, id: &str) {
-        let mut dependencies = self.find_dependencies(id);
-
-        self.selected.append(&mut dependencies);
-        self.selected.sort();
-        self.selected.dedup();
-    }
-
-    pub fn unselect(&mut self, id: &str) {
-        let dependants = self.find_dependants(id);
-
-        // Should be faster?
-        let dependants_set: HashSet<String> = dependants.into_iter().collect();
-
-        self.selected.retain(|e| !dependants_set.contains(e)); // Do not remove required component
-
-    }
-
-    pub fn find_dependants(&self, id: &str) -> Vec<String> {
-        let mut dependants: Vec<String> = Vec::new();
-        let components = get_all_components(&self);
-
-        if components.iter().find(|&c| c.id == id).is_some() {
-            // Is a component
-            self.find_dependants_recursive(id, &components, &mut dependants);
-            dependants.push(id.to_owned());
-        } else if let Some(category) = self.find_category_by_id(id) {
-            // Is a category
-            let mut category_components = vec![];
-            collect_category_components(category, &mut category_components);
-            for comp in category_components.iter() {
-                self.find_dependants_recursive(&comp.id, &components, &mut dependants);
-                dependants.push(comp.id.clone());
-            }
-        }
-
-        // Since recursion can add duplicates, ensure unique elements
-        dependants.retain(|e| !self.required.contains(e)); // Remove all required components from dependants list
-        dependants.sort();
-        dependants.dedup();
-
-        dependants
-    }
+// Nearest directory that exists, the installation path usually hasn't been created yet
+fn existing_ancestor(path: &Path) -> std::io::Result<PathBuf> {
+    let absolute = std::env::current_dir()?.join(path);
+    absolute.ancestors()
+        .find(|p| p.exists())
+        .map(|p| p.to_path_buf())
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, format!("No existing directory for {:?}", path)))
+}
 
-    fn find_dependants_recursive<'a>(
-        &self,
-        id: &str,
-        components: &Vec<&'a Component>,
-        dependants: &mut Vec<String>,
-    ) {
-        for component in components.iter() {
-            if let Some(depends) = &component.depends {
-                let dependencies: Vec<&str> = depends.split_whitespace().collect();
-
-                if dependencies.contains(&id) {
-                    // If not already included, add to dependants and search for its dependants
-                    if !dependants.contains(&component.id) {
-                        dependants.push(component.id.clone());
-                        self.find_dependants_recursive(&component.id, components, dependants);
-                    }
-                }
-            }
+fn same_volume(a: &Path, b: &Path) -> bool {
+    #[cfg(target_os = "windows")]
+    {
+        match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+            (Ok(a), Ok(b)) => a.components().next() == b.components().next(),
+            _ => false,
         }
     }
-
-    pub fn find_dependencies(&self, id: &str) -> Vec<String> {
-        let mut dependencies: Vec<String> = Vec::new();
-        let components = get_all_components(&self);
-
-        if let Some(component) = components.iter().find(|&c| c.id == id) {
-            // Is a component
-            self.find_dependencies_recursive(component, &components, &mut dependencies);
-            // Always select self as well as dependencies
-            dependencies.push(id.to_owned());
-        } else if let Some(category) = self.find_category_by_id(id) {
-            // Is a category
-            let mut category_components = vec![];
-            collect_category_components(category, &mut category_components);
-            for comp in category_components.iter() {
-                self.find_dependencies_recursive(comp, &components, &mut dependencies);
-                dependencies.push(comp.id.clone());
-            }
+    #[cfg(not(target_os = "windows"))]
+    {
+        use std::os::unix::fs::MetadataExt;
+        match (std::fs::metadata(a), std::fs::metadata(b)) {
+            (Ok(a), Ok(b)) => a.dev() == b.dev(),
+            _ => false,
         }
-        // Since recursion might add duplicates, ensure unique elements
-        dependencies.sort();
-        dependencies.dedup();
-
-        dependencies
     }
+}
 
-    fn find_dependencies_recursive<'a>(
-        &self,
-        component: &'a Component,
-        components: &Vec<&'a Component>,
-        dependencies: &mut Vec<String>,
-    ) {
-        if let Some(depends) = &component.depends {
-            let direct_dependencies: Vec<&str> = depends.split_whitespace().collect();
-
-            for dep_id in direct_dependencies.iter() {
-                // Avoid adding duplicate dependencies
-                if !dependencies.contains(&dep_id.to_string()) {
-                    dependencies.push(dep_id.to_string());
-
-                    // Find the component that matches this dependency ID and recursively find its dependencies
-                    if let Some(dep_component) = components.iter().find(|&c| &c.id == *dep_id) {
-                        self.find_dependencies_recursive(dep_component, components, dependencies);
-                    }
-                }
-            }
+// Check a detached minisign signature against each trusted public key
+fn verify_component_list(data: &[u8], signature: &str, public_keys: &[String]) -> Result<(), Error> {
+    let signature = Signature::decode(signature)
+        .map_err(|e| Error::SignatureError(format!("invalid signature: {}", e)))?;
+    for key in public_keys {
+        let public_key = PublicKey::from_base64(key.trim())
+            .map_err(|e| Error::SignatureError(format!("invalid public key {:?}: {}", key, e)))?;
+        if public_key.verify(data, &signature, false).is_ok() {
+            return Ok(());
         }
     }
+    Err(Error::SignatureError("component list is not signed by a trusted key".to_owned()))
+}
 
-    // Function to find a category by ID and return a reference to it
-    fn find_category_by_id(&self, id: &str) -> Option<&Category> {
-        self.find_category_recursive(&self.categories, id)
-    }
-
-    fn find_category_recursive<'a>(&'a self, categories: &'a [Category], id: &str) -> Option<&'a Category> {
-        for category in categories {
-            if category.id == id {
-                return Some(category);
-            }
-            if let Some(subcategory) = self.find_category_recursive(&category.subcategories, id) {
-                return Some(subcategory);
+fn installation_path_is_safe(dir: &str) -> std::io::Result<bool> {
+    match std::fs::read_dir(dir) {
+        Ok(mut entries) => Ok(!entries.next().is_some()),
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                // Bad installation path, alert and return early
+                Ok(true)
+            } else {
+                Err(e)
             }
         }
-        None
-    }
-}
-
-fn collect_category_components<'a>(category: &'a Category, components: &mut Vec<&'a Component>) {
-    for subcat in category.subcategories.iter() {
-        collect_category_components(subcat, components);
-    }
-    for component in &category.components {
-        components.push(component);
     }
 }
-
-fn collect_components<'a>(categories: &'a [Category], components: &mut Vec<&'a Component>) {
-    for category in categories {
-        // Add all components in the current category to the components vector
-        collect_category_components(&category, components);
-    }
-}
-
-// Utility function to initiate the collection process and return the result
-fn get_all_components<'a>(list: &'a ComponentList) -> Vec<&'a Component> {
-    let mut components = Vec::new();
-    collect_components(&list.categories, &mut components);
-    components
-}
-
-
-#[derive(Serialize, Deserialize, Clone)]
-pub struct DownloadState {
-    pub total_size: u64,
-    pub total_downloaded: u64,
-    pub total_components: usize,
-    pub component_number: i32,
-    pub current: Option<Component>,
-    pub stage: String,
-}
-
-impl Default for DownloadState {
-    fn default() -> Self {
-        DownloadState {
-            total_size: 0,
-            total_downloaded: 0,
-            total_components: 0,
-            component_number: 0,
-            current: None,
-            stage: String::new(),
-        }
-    }
-}
\ No newline at end of file
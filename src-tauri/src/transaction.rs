@@ -0,0 +1,202 @@
+use std::path::{Path, PathBuf};
+
+use crate::staging::StagingDir;
+
+// Records every change made to the installation directory so a failed or cancelled
+// install can put it back exactly how it was found
+pub struct InstallTransaction {
+    base_dir: PathBuf,
+    // Unique to this transaction so backups kept by an earlier failed rollback are never touched,
+    // and not removed on drop so a transaction lost to a panic still leaves them behind
+    backup_dir: PathBuf,
+    created_dirs: Vec<PathBuf>,
+    created_files: Vec<PathBuf>,
+    // Original path, and where the file was moved to before being overwritten
+    backups: Vec<(PathBuf, PathBuf)>,
+    staging: Option<StagingDir>,
+}
+
+impl InstallTransaction {
+    // Staging defaults to the installation path, keeping it on the same volume so files can be renamed into place
+    pub fn begin(base_dir: &Path, staging_parent: Option<&Path>) -> std::io::Result<Self> {
+        let mut transaction = InstallTransaction {
+            base_dir: base_dir.to_path_buf(),
+            backup_dir: PathBuf::new(),
+            created_dirs: vec![],
+            created_files: vec![],
+            backups: vec![],
+            staging: None,
+        };
+        transaction.create_dir_all(base_dir)?;
+        let staging = StagingDir::create(staging_parent.unwrap_or(base_dir))?;
+        transaction.backup_dir = StagingDir::create_named(base_dir, "backup")?.keep();
+        transaction.staging = Some(staging);
+        Ok(transaction)
+    }
+
+    pub fn staging_dir(&self) -> &StagingDir {
+        // Always set by begin
+        self.staging.as_ref().unwrap()
+    }
+
+    pub fn base_dir(&self) -> &Path {
+        &self.base_dir
+    }
+
+    // Create a directory and any missing parents, remembering which ones didn't exist before
+    pub fn create_dir_all(&mut self, path: &Path) -> std::io::Result<()> {
+        let mut missing: Vec<PathBuf> = path.ancestors()
+            .take_while(|p| !p.as_os_str().is_empty() && !p.exists())
+            .map(|p| p.to_path_buf())
+            .collect();
+        std::fs::create_dir_all(path)?;
+        // Parents first, so rollback can remove them in reverse
+        missing.reverse();
+        for dir in missing {
+            if !self.created_dirs.contains(&dir) {
+                self.created_dirs.push(dir);
+            }
+        }
+        Ok(())
+    }
+
+    // Move a file into place, moving aside whatever was there before the install started
+    pub fn move_file(&mut self, src: &Path, dest: &Path) -> std::io::Result<()> {
+        self.prepare_overwrite(dest)?;
+        move_file(src, dest)
+    }
+
+    pub fn write_file(&mut self, dest: &Path, contents: &[u8]) -> std::io::Result<()> {
+        if let Some(parent) = dest.parent() {
+            self.create_dir_all(parent)?;
+        }
+        self.prepare_overwrite(dest)?;
+        std::fs::write(dest, contents)
+    }
+
+    // Remove a file, keeping a backup if it existed before the install started
+    pub fn remove_file(&mut self, path: &Path) -> std::io::Result<()> {
+        if !path.exists() {
+            return Ok(());
+        }
+        self.prepare_overwrite(path)?;
+        remove_file_if_exists(path)
+    }
+
+    fn prepare_overwrite(&mut self, dest: &Path) -> std::io::Result<()> {
+        if dest.exists() && !self.is_tracked(dest) {
+            let relative_path = dest.strip_prefix(&self.base_dir)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+            let backup_path = self.backup_dir.join(relative_path);
+            if let Some(parent) = backup_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            move_file(dest, &backup_path)?;
+            self.backups.push((dest.to_path_buf(), backup_path));
+        } else if !dest.exists() {
+            self.created_files.push(dest.to_path_buf());
+        }
+        Ok(())
+    }
+
+    fn is_tracked(&self, path: &Path) -> bool {
+        self.created_files.iter().any(|p| p == path) || self.backups.iter().any(|(p, _)| p == path)
+    }
+
+    // Everything installed, backups of overwritten files are no longer needed
+    pub fn commit(mut self) -> std::io::Result<()> {
+        drop(self.staging.take());
+        remove_dir_if_exists(&self.backup_dir)
+    }
+
+    // Best effort, one file that can't be restored shouldn't strand the others in the backup directory
+    pub fn rollback(mut self) -> std::io::Result<()> {
+        drop(self.staging.take());
+        let mut errors = vec![];
+        for file in self.created_files.iter().rev() {
+            if let Err(e) = remove_file_if_exists(file) {
+                errors.push(format!("{:?}: {}", file, e));
+            }
+        }
+        let mut restored_all = true;
+        for (original, backup) in self.backups.iter().rev() {
+            let _ = std::fs::remove_file(original);
+            // Parent may have been pruned if the file was removed
+            let restored = match original.parent() {
+                Some(parent) => std::fs::create_dir_all(parent),
+                None => Ok(()),
+            }.and_then(|_| move_file(backup, original));
+            if let Err(e) = restored {
+                restored_all = false;
+                errors.push(format!("{:?}: {}", original, e));
+            }
+        }
+        // Nothing in a created directory existed before, so remove it entirely
+        for dir in self.created_dirs.iter().rev() {
+            if let Err(e) = remove_dir_if_exists(dir) {
+                errors.push(format!("{:?}: {}", dir, e));
+            }
+        }
+        if restored_all {
+            if let Err(e) = remove_dir_if_exists(&self.backup_dir) {
+                errors.push(format!("{:?}: {}", self.backup_dir, e));
+            }
+        } else {
+            errors.push(format!("files that could not be restored are kept in {:?}", self.backup_dir));
+        }
+        if !errors.is_empty() {
+            return Err(std::io::Error::other(errors.join(", ")));
+        }
+        Ok(())
+    }
+}
+
+pub fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if std::fs::rename(from, to).is_err() {
+        // Rename can't cross volumes, copy instead
+        std::fs::copy(from, to)?;
+        std::fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+fn remove_file_if_exists(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn remove_dir_if_exists(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_dir_all(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backups_kept_by_an_earlier_run_are_left_alone() {
+        let dir = StagingDir::create(&std::env::temp_dir().join("fp-bootstrapper-tests")).unwrap();
+        let base_dir = dir.path().join("install");
+        std::fs::create_dir_all(&base_dir).unwrap();
+        std::fs::write(base_dir.join("data.txt"), "old").unwrap();
+        // Stands in for the backup directory of a rollback that couldn't restore everything
+        let kept = StagingDir::create_named(&base_dir, "backup").unwrap().keep();
+        std::fs::write(kept.join("data.txt"), "kept").unwrap();
+
+        let mut transaction = InstallTransaction::begin(&base_dir, None).unwrap();
+        assert_ne!(transaction.backup_dir, kept);
+        transaction.write_file(&base_dir.join("data.txt"), b"new").unwrap();
+        transaction.rollback().unwrap();
+        assert_eq!(std::fs::read_to_string(base_dir.join("data.txt")).unwrap(), "old");
+
+        let mut transaction = InstallTransaction::begin(&base_dir, None).unwrap();
+        transaction.write_file(&base_dir.join("data.txt"), b"new").unwrap();
+        transaction.commit().unwrap();
+        assert_eq!(std::fs::read_to_string(kept.join("data.txt")).unwrap(), "kept");
+    }
+}
@@ -7,10 +7,11 @@ import "./App.css";
 import { InstallationPage } from './pages/InstallationPage';
 import { SetupComponentsPage } from './pages/SetupComponentsPage';
 import { SetupPage } from './pages/SetupPage';
-import { DownloadState, setDownloadState, setSelected, setState } from './redux/state/stateSlice';
+import { DownloadState, InstallationCancelled, setCancelled, setDownloadState, setSelected, setState } from './redux/state/stateSlice';
 import { RootState } from './redux/store';
 import { AppState, View } from './types';
 import { FailurePage } from './pages/FailurePage';
+import { AbortPage } from './pages/AbortPage';
 
 function App() {
   const { appState, busy } = useSelector((state: RootState) => state.state);
@@ -39,6 +40,10 @@ function App() {
       .catch(setFatalError);
     });
 
+    listen<InstallationCancelled>('installation_cancelled', (event) => {
+      dispatch(setCancelled(event.payload));
+    });
+
     // Listen for any fatal errors
     listen<string>('fatal_error', (event) => {
       setFatalError(event.payload);
@@ -62,6 +67,9 @@ function App() {
       case View.INSTALLATION: {
         return <InstallationPage />
       }
+      case View.ABORTED: {
+        return <AbortPage />
+      }
       default: {
         return <>
           {appState.view}
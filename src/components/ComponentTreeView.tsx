@@ -22,7 +22,7 @@ type NodeRender = {
 
 export function ComponentsTreeView() {
   const { appState } = useSelector((state: RootState) => state.state);
-  const { selected, required } = appState.components;
+  const { selected, required, updates, repairs } = appState.components;
   const dispatch = useDispatch();
   const [expanded, setExpanded] = useState<string[]>([]);
   console.log(required);
@@ -65,6 +65,17 @@ export function ComponentsTreeView() {
               disabled={required.includes(component.id)}
               checked={selected.includes(component.id) || required.includes(component.id)}/>
             {`${component.name} - ${readableByteSize(component.install_size)}`}
+            {component.installed && (
+              repairs.includes(component.id) ? ' (Repair Needed)' :
+              updates.includes(component.id) ? ' (Update Available)' :
+              ' (Installed)'
+            )}
+            {component.installed && !required.includes(component.id) && (
+              <button onClick={(event) => {
+                event.stopPropagation();
+                uninstallComponent(component.id);
+              }}>Uninstall</button>
+            )}
           </div>
         );
       }
@@ -114,7 +125,23 @@ export function ComponentsTreeView() {
       render,
       combinedState
     };
-  }, [expanded, selected, required]);
+  }, [expanded, selected, required, updates, repairs]);
+
+  const uninstallComponent = async (id: string) => {
+    try {
+      const dependants: string[] = await invoke("find_component_dependants", { id });
+      const others = dependants.filter(d => d !== id);
+      const prompt = others.length > 0 ?
+        `Uninstalling "${id}" will also uninstall ${others.length} other components (${others.join(', ')}). Is this okay?` :
+        `Uninstall "${id}"?`;
+      if (await ask(prompt)) {
+        // Dependants were part of the prompt, so it's fine to remove them too
+        await invoke('uninstall_component', { id, cascade: true });
+      }
+    } catch (error) {
+      message(`${error}`, 'Error');
+    }
+  };
 
   const toggleComponent = async (id: string, newState?: boolean): Promise<boolean> => {
     console.log(id);
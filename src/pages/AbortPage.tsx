@@ -0,0 +1,22 @@
+import { useSelector } from "react-redux";
+import { RootState } from "../redux/store";
+
+export function AbortPage() {
+  const { cancelled } = useSelector((state: RootState) => state.state);
+
+  return (
+    <div>
+      <h1 className="title">Installation Aborted</h1>
+      <h3>Temporary files have been removed and the installation folder has been restored.</h3>
+      { cancelled && (
+        <>
+          <h3>{`Installed: ${cancelled.installed.length > 0 ? cancelled.installed.join(', ') : 'None'}`}</h3>
+          <h3>{`Not Installed: ${cancelled.not_installed.length > 0 ? cancelled.not_installed.join(', ') : 'None'}`}</h3>
+          { cancelled.rolled_back.length > 0 && (
+            <h3>{`Removed Again: ${cancelled.rolled_back.join(', ')}`}</h3>
+          )}
+        </>
+      )}
+    </div>
+  );
+}
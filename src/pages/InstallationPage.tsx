@@ -1,11 +1,15 @@
-import { useSelector } from "react-redux";
+import { useDispatch, useSelector } from "react-redux";
 import { RootState } from "../redux/store";
 import { Box, Button, LinearProgress } from "@mui/material";
+import { invoke } from "@tauri-apps/api";
+import { message } from "@tauri-apps/api/dialog";
+import { setBusy } from "../redux/state/stateSlice";
 import { readableByteSize } from "../util";
 import { useMemo } from "react";
 
 export function InstallationPage() {
   const { downloadState } = useSelector((state: RootState) => state.state);
+  const dispatch = useDispatch();
   const percent = (downloadState.total_downloaded / downloadState.total_size) * 100;
 
   const startTime = useMemo(() => Date.now(), []);
@@ -19,12 +23,29 @@ export function InstallationPage() {
         <h3>{percent.toFixed(1)}% Progress {`(${readableByteSize(downloadState.total_downloaded)} - ${readableByteSize(downloadState.total_size)})`}</h3>
         <LinearProgress variant="determinate" value={percent}/>
         <h3>{`${(downloadState.total_components + 1) - downloadState.component_number} Components Remaining...`}</h3>
-        <h2>{`Installing: ${downloadState.current?.name}`}</h2>
+        {downloadState.in_progress.map((progress) => (
+          <Box key={progress.component.id}>
+            <h3>{`${progress.component.name} - ${progress.stage} (${readableByteSize(progress.downloaded)} - ${readableByteSize(progress.component.download_size)})`}</h3>
+            <LinearProgress variant="determinate" value={(progress.downloaded / progress.component.download_size) * 100}/>
+            {progress.attempt > 1 && (
+              <h4>{`Attempt ${progress.attempt} - ${progress.last_error}`}</h4>
+            )}
+          </Box>
+        ))}
         <h3>{downloadState.stage === "Downloading" ? `Download Rate: ${readableByteSize(downloadRate)}/s` : '...'}</h3>
         <h3>{`${downloadState.stage}...`}</h3>
       </Box>
       <Box className='box-row'>
-        <Button variant="contained">
+        <Button variant="contained" onClick={() => {
+          dispatch(setBusy(true));
+          invoke('cancel_installation')
+          .catch((error) => {
+            message(error, 'Error');
+          })
+          .finally(() => {
+            dispatch(setBusy(false));
+          });
+        }}>
           Abort
         </Button>
       </Box>
@@ -1,17 +1,22 @@
 import { Box, Button } from "@mui/material";
 import { ComponentsTreeView } from "../components/ComponentTreeView";
-import { useDispatch } from "react-redux";
+import { useDispatch, useSelector } from "react-redux";
+import { RootState } from "../redux/store";
 import { invoke } from "@tauri-apps/api";
 import { setBusy } from "../redux/state/stateSlice";
-import { View } from "../types";
+import { ComponentVerification, View } from "../types";
 import { message } from "@tauri-apps/api/dialog";
 
 export function SetupComponentsPage() {
+  const { appState } = useSelector((state: RootState) => state.state);
   const dispatch = useDispatch();
   
   return (
     <div className='vertical-box'>
-      <h1 className='title'>Installation Options</h1>
+      <h1 className='title'>{appState.updating ? 'Update Options' : 'Installation Options'}</h1>
+      { appState.updating && (
+        <h3>{`${appState.components.updates.length} installed components have updates available`}</h3>
+      )}
       <Box className='scroll-box'>
         <ComponentsTreeView />
       </Box>
@@ -27,6 +32,26 @@ export function SetupComponentsPage() {
             dispatch(setBusy(false));
           });
         }}>Setup</Button>
+        { appState.updating && (
+          <Button variant='contained' onClick={() => {
+            dispatch(setBusy(true));
+            invoke<ComponentVerification[]>('verify_installation')
+            .then((report) => {
+              if (report.length === 0) {
+                message('All installed files are intact.', 'Verify');
+              } else {
+                const lines = report.map((r) => `${r.id}: ${r.missing.length} missing, ${r.modified.length} modified${r.repairable ? '' : ' (cannot be repaired)'}`);
+                message(`Damaged components will be repaired on update.\n\n${lines.join('\n')}`, 'Verify');
+              }
+            })
+            .catch((error) => {
+              message(error, 'Error');
+            })
+            .finally(() => {
+              dispatch(setBusy(false));
+            });
+          }}>Verify</Button>
+        )}
         <Button variant='contained' onClick={() => {
           dispatch(setBusy(true));
           invoke('change_view', { view: View.INSTALLATION })
@@ -36,7 +61,7 @@ export function SetupComponentsPage() {
           .finally(() => {
             dispatch(setBusy(false));
           });
-        }}>Install</Button>
+        }}>{appState.updating ? 'Update' : 'Install'}</Button>
       </Box>
     </div>
   )
@@ -6,26 +6,44 @@ const initialState: AppState = {
   installation_target: OperatingSystem.LINUX,
   installation_path: './Flashpoint',
   installation_channel: 'Stable',
-  components: { url: '', categories: [], selected: [], required: [] },
+  updating: false,
+  components: { url: '', mirrors: [], categories: [], selected: [], required: [], updates: [], repairs: [] },
   view: View.SETUP,
   config: {
     name: 'Example App',
   },
 }
 
+export type ComponentProgress = {
+  component: Component,
+  downloaded: number,
+  stage: string,
+  attempt: number,
+  last_error?: string,
+  mirror?: string,
+}
+
 export type DownloadState = {
   total_size: number,
   total_downloaded: number,
   total_components: number,
   component_number: number,
-  current?: Component,
+  in_progress: ComponentProgress[],
+  mirrors: Record<string, string>,
   stage: String,
 }
 
+export type InstallationCancelled = {
+  installed: string[],
+  not_installed: string[],
+  rolled_back: string[],
+}
+
 export type GlobalState = {
   appState: AppState,
   busy: boolean,
   downloadState: DownloadState,
+  cancelled?: InstallationCancelled,
 };
 
 export const stateSlice = createSlice({
@@ -39,9 +57,11 @@ export const stateSlice = createSlice({
       total_downloaded: 0,
       component_number: 0,
       total_components: 0,
-      current: undefined,
+      in_progress: [],
+      mirrors: {},
       stage: 'Downloading',
-    }
+    },
+    cancelled: undefined,
   } as GlobalState,
   reducers: {
     setState: (state, action: PayloadAction<AppState>) => {
@@ -56,9 +76,12 @@ export const stateSlice = createSlice({
     setDownloadState: (state, action: PayloadAction<DownloadState>) => {
       state.downloadState = action.payload;
     },
+    setCancelled: (state, action: PayloadAction<InstallationCancelled>) => {
+      state.cancelled = action.payload;
+    },
   },
 });
 
-export const { setState, setSelected, setBusy, setDownloadState } = stateSlice.actions;
+export const { setState, setSelected, setBusy, setDownloadState, setCancelled } = stateSlice.actions;
 
 export default stateSlice.reducer;
\ No newline at end of file
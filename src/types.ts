@@ -9,6 +9,7 @@ export enum View {
   SETUPSELECT = "SETUPSELECT",
   INSTALLATION = "INSTALLATION",
   FINISHED = "FINISHED",
+  ABORTED = "ABORTED",
 }
 
 export type OsConfig = {
@@ -30,6 +31,7 @@ export type AppState = {
   installation_target: OperatingSystem;
   installation_path: string;
   installation_channel: string;
+  updating: boolean;
   components: ComponentList;
   config: AppConfig;
 }
@@ -50,9 +52,19 @@ export function osToName(os: OperatingSystem) {
 
 export type ComponentList = {
   url: string;
+  mirrors: string[];
   categories: Category[];
   selected: string[];
   required: string[];
+  updates: string[];
+  repairs: string[];
+}
+
+export type ComponentVerification = {
+  id: string;
+  missing: string[];
+  modified: string[];
+  repairable: boolean;
 }
 
 export type Category = {
@@ -72,7 +84,10 @@ export type Component = {
   install_size: number;
   path?: string;
   hash: string;
+  sha256?: string;
   depends?: string;
   required: boolean;
   installed: boolean;
+  preserve?: string;
+  merge?: string;
 }
\ No newline at end of file